@@ -49,13 +49,31 @@ pub fn tem_fn(input: TokenStream) -> TokenStream {
         quote! { Selector::#variant( #( #selector_varnames ), * ) }
     };
 
+    // Functions returning `Result<f64, Error>` are fallible, their errors are cached too.
+    let is_fallible = match &sig.output {
+        syn::ReturnType::Type(_, ty) => match ty.as_ref() {
+            syn::Type::Path(path) => path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Result"),
+            _ => false,
+        },
+        syn::ReturnType::Default => false,
+    };
+    let register_fn = if is_fallible {
+        quote! { try_register_fn }
+    } else {
+        quote! { register_fn }
+    };
+
     let code = quote! {
         #[allow(non_snake_case)]
         #sig {
-            db.register_fn(#name, #selector, t, |db| #body)
+            db.#register_fn(#name, #selector, t, move |db| #body)
         }
     };
 
-    code.into()
+    code
 }
 
@@ -1,7 +1,8 @@
 use std::ops::Add;
 
-use chrono::{DateTime, FixedOffset, Utc};
-use petgraph::matrix_graph::NodeIndex;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
 
 pub type Index = NodeIndex<usize>;
 
@@ -49,38 +50,61 @@ pub type Index = NodeIndex<usize>;
 //     }
 // }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, sqlx::Type)]
-pub struct Time(pub i64);
+const MICROS_PER_SECOND: i64 = 1_000_000;
+
+/// Point in time, in microseconds since the UNIX epoch (UTC).
+///
+/// Offsets given as plain integers, like `t + 3600`, are in seconds. Sub-second offsets
+/// are added as a [`Duration`], e.g. `t + Duration::milliseconds(500)`. The raw value is
+/// private, so that the unit is always explicit, e.g. [`Time::from_timestamp`] or
+/// [`Time::from_timestamp_micros`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, sqlx::Type, Serialize, Deserialize)]
+pub struct Time(pub(crate) i64);
 
 impl Time {
     pub fn now() -> Self {
-        Time(Utc::now().timestamp())
+        Time::from_datetime(Utc::now())
     }
 
     pub fn from_string(s: &str) -> Result<Self, chrono::ParseError> {
         Ok(Time(
-            DateTime::<FixedOffset>::parse_from_rfc3339(s)?.timestamp(),
+            DateTime::<FixedOffset>::parse_from_rfc3339(s)?.timestamp_micros(),
         ))
     }
 
     pub fn from_datetime(dt: DateTime<Utc>) -> Self {
-        Time(dt.timestamp())
+        Time(dt.timestamp_micros())
     }
 
     pub fn from_naive_datetime(dt: chrono::NaiveDateTime) -> Self {
-        Time(DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc).timestamp())
+        Time(dt.and_utc().timestamp_micros())
     }
 
+    /// Time from a UNIX timestamp in seconds.
+    ///
+    /// Panics if the time is out of the range of [`Time`], i.e. about ±292,000 years.
     pub fn from_timestamp(timestamp: i64) -> Self {
-        Time(timestamp)
+        match timestamp.checked_mul(MICROS_PER_SECOND) {
+            Some(timestamp_micros) => Time(timestamp_micros),
+            None => panic!("Timestamp {} is out of range", timestamp),
+        }
+    }
+
+    pub fn from_timestamp_micros(timestamp_micros: i64) -> Self {
+        Time(timestamp_micros)
     }
 
+    /// UNIX timestamp in whole seconds, rounded down.
     pub fn timestamp(&self) -> i64 {
+        self.0.div_euclid(MICROS_PER_SECOND)
+    }
+
+    pub fn timestamp_micros(&self) -> i64 {
         self.0
     }
 
     pub fn as_datetime(&self) -> DateTime<Utc> {
-        DateTime::<Utc>::from_timestamp(self.0, 0).unwrap()
+        DateTime::<Utc>::from_timestamp_micros(self.0).unwrap()
     }
 
     pub fn as_naive_datetime(&self) -> chrono::NaiveDateTime {
@@ -96,37 +120,172 @@ impl Time {
     }
 }
 
-impl ToString for Time {
-    fn to_string(&self) -> String {
-        self.as_datetime().to_rfc3339()
+/// Times from `start` (inclusive) to `end` (exclusive) spaced by `step`, e.g.
+/// `TimeRange::new(start, end, Duration::minutes(15))`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeRange {
+    pub start: Time,
+    pub end: Time,
+    /// At least one microsecond, the resolution of [`Time`].
+    pub step: Duration,
+}
+
+impl TimeRange {
+    pub fn new(start: Time, end: Time, step: Duration) -> Self {
+        let range = TimeRange { start, end, step };
+        // Fail early on an invalid step.
+        range.step_micros();
+        range
+    }
+
+    fn step_micros(&self) -> i128 {
+        match self.step.num_microseconds() {
+            Some(step) if step > 0 => step as i128,
+            _ => panic!(
+                "TimeRange step {} is not a positive number of microseconds",
+                self.step
+            ),
+        }
+    }
+
+    /// Panics if the number of times does not fit in `usize`.
+    pub fn len(&self) -> usize {
+        if self.end <= self.start {
+            return 0;
+        }
+        let step = self.step_micros();
+        let len = (self.end.0 as i128 - self.start.0 as i128 + step - 1) / step;
+        usize::try_from(len).expect("TimeRange is too long")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn times(&self) -> impl Iterator<Item = Time> {
+        let start = self.start.0 as i128;
+        let step = self.step_micros();
+        // Every time is before `end`, so it is in range.
+        (0..self.len()).map(move |i| Time((start + i as i128 * step) as i64))
+    }
+}
+
+/// Times out of the range of `chrono`, like the `Time(i64::MIN)` sentinel, are printed as
+/// the raw number of microseconds.
+impl std::fmt::Display for Time {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match DateTime::<Utc>::from_timestamp_micros(self.0) {
+            Some(dt) => f.write_str(&dt.to_rfc3339()),
+            None => write!(f, "Time({})", self.0),
+        }
     }
 }
 
+/// Time from a UNIX timestamp in seconds, like [`Time::from_timestamp`].
 impl From<i64> for Time {
     fn from(i: i64) -> Self {
-        Time(i)
+        Time::from_timestamp(i)
     }
 }
 
+/// Panics if the sum is out of the range of [`Time`].
 impl Add for Time {
     type Output = Time;
 
     fn add(self, rhs: Self) -> Self::Output {
-        Time(self.0 + rhs.0)
+        match self.0.checked_add(rhs.0) {
+            Some(sum) => Time(sum),
+            None => panic!("Time {:?} + {:?} is out of range", self, rhs),
+        }
     }
 }
 
+/// Panics if the sum is out of the range of [`Time`].
+impl Add<Duration> for Time {
+    type Output = Time;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        match rhs.num_microseconds() {
+            Some(rhs) => self + Time(rhs),
+            None => panic!("Duration {} is out of range", rhs),
+        }
+    }
+}
+
+/// Add `rhs` seconds.
 impl Add<i64> for Time {
     type Output = Time;
 
     fn add(self, rhs: i64) -> Self::Output {
-        Time(self.0 + rhs)
+        self + Time::from_timestamp(rhs)
     }
 }
 
 impl std::fmt::Debug for Time {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let datetime = DateTime::<Utc>::from_timestamp(self.0, 0).unwrap();
-        write!(f, "{}", datetime.naive_utc())
+        match DateTime::<Utc>::from_timestamp_micros(self.0) {
+            Some(dt) => write!(f, "{}", dt.naive_utc()),
+            None => write!(f, "Time({})", self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_out_of_the_range_of_chrono_are_printed_raw() {
+        assert_eq!(
+            format!("{:?}", Time::from_timestamp(0)),
+            "1970-01-01 00:00:00"
+        );
+        assert_eq!(
+            Time::from_timestamp(0).to_string(),
+            "1970-01-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            format!("{:?}", Time(i64::MIN)),
+            format!("Time({})", i64::MIN)
+        );
+        assert_eq!(Time(i64::MAX).to_string(), format!("Time({})", i64::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn timestamp_out_of_range_panics() {
+        Time::from_timestamp(i64::MAX / 1000);
+    }
+
+    #[test]
+    fn time_ranges_have_sub_second_steps() {
+        let start = Time::from_timestamp(10);
+        let range = TimeRange::new(start, start + 2, Duration::milliseconds(500));
+        assert_eq!(range.len(), 4);
+        let times: Vec<Time> = range.times().collect();
+        assert_eq!(times[1], start + Duration::milliseconds(500));
+        assert_eq!(times[3], Time::from_timestamp_micros(11_500_000));
+
+        let range = TimeRange::new(Time(i64::MIN), Time(i64::MAX), Duration::days(1));
+        assert_eq!(range.len(), 213_503_983);
+        let end = Time(i64::MAX);
+        let range = TimeRange::new(Time(i64::MAX - 10), end, Duration::microseconds(4));
+        let times: Vec<Time> = range.times().collect();
+        assert_eq!(
+            times,
+            vec![Time(i64::MAX - 10), Time(i64::MAX - 6), Time(i64::MAX - 2)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not a positive number of microseconds")]
+    fn time_range_step_below_a_microsecond_panics() {
+        TimeRange::new(Time(0), Time(10), Duration::nanoseconds(500));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn time_overflow_panics() {
+        let _ = Time(i64::MAX) + Duration::microseconds(1);
     }
 }
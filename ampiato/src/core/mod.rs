@@ -1,16 +1,37 @@
 pub mod defs;
+pub mod value;
 
 use std::error::Error as StdError;
+use std::sync::Arc;
 
 use defs::Time;
+use value::Value;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Error {
     UnexpectedNumberOfColumns { actual: usize, expected: usize },
     UnknownTable { table_name: String },
+    UnknownColumn { table_name: String, column_name: String },
     ReplicationNotEnabled,
-    DatabaseError(sqlx::Error),
-    ReplicationError(String)
+    DatabaseError(Arc<sqlx::Error>),
+    ReplicationError(String),
+    SnapshotError(String),
+    /// A function depends on its own value. The path starts and ends with the same node.
+    CyclicDependency { path: Vec<String> },
+    /// A value read by a function is not in the value provider. `call_chain` lists the
+    /// functions being evaluated when the value was read, outermost first.
+    MissingValue {
+        name: &'static str,
+        selector: String,
+        time: Time,
+        call_chain: Vec<String>,
+    },
+    /// A value has a different type than the one it is read as.
+    UnexpectedValueType {
+        name: &'static str,
+        expected: &'static str,
+        actual: &'static str,
+    },
 }
 
 impl std::fmt::Display for Error {
@@ -23,6 +44,9 @@ impl std::fmt::Display for Error {
             Error::UnknownTable { table_name } => {
                 f.write_fmt(format_args!("Unknown table: {}", table_name))
             },
+            Error::UnknownColumn { table_name, column_name } => {
+                f.write_fmt(format_args!("Unknown column: {}.{}", table_name, column_name))
+            },
             Error::ReplicationNotEnabled => {
                 f.write_str("Replication not enabled")
             },
@@ -31,7 +55,24 @@ impl std::fmt::Display for Error {
             },
             Error::ReplicationError(e) => {
                 f.write_fmt(format_args!("Replication error: {}", e))
-            }
+            },
+            Error::SnapshotError(e) => {
+                f.write_fmt(format_args!("Snapshot error: {}", e))
+            },
+            Error::CyclicDependency { path } => {
+                f.write_fmt(format_args!("Cyclic dependency: {}", path.join(" -> ")))
+            },
+            Error::MissingValue { name, selector, time, call_chain } => {
+                f.write_fmt(format_args!("Missing value: {}({}, {:?})", name, selector, time))?;
+                if !call_chain.is_empty() {
+                    f.write_fmt(format_args!(" read by {}", call_chain.join(" -> ")))?;
+                }
+                Ok(())
+            },
+            Error::UnexpectedValueType { name, expected, actual } => f.write_fmt(format_args!(
+                "Unexpected type of {}: actual: {}, expected: {}",
+                name, actual, expected
+            )),
         }
     }
 }
@@ -40,7 +81,7 @@ pub type BoxDynError = Box<dyn StdError + 'static + Send + Sync>;
 
 impl From<sqlx::Error> for Error {
     fn from(e: sqlx::Error) -> Self {
-        Error::DatabaseError(e)
+        Error::DatabaseError(Arc::new(e))
     }
 }
 
@@ -57,5 +98,6 @@ pub trait TableMetadata: Sized {
 pub trait TableValues<Selector> {
     fn time(&self) -> Time;
     fn selector(&self) -> Selector;
-    fn values(&self) -> Vec<(&'static str, &f64)>;
+    /// Values of the row, `None` for the NULL ones.
+    fn values(&self) -> Vec<(&'static str, Option<Value>)>;
 }
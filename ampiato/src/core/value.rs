@@ -0,0 +1,234 @@
+use std::sync::Arc;
+
+use super::Error;
+
+/// Value of a quantity or a function result.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Float(f64),
+    Int(i64),
+    Bool(bool),
+    /// Categorical value, e.g. a fuel type or a unit state.
+    Str(Arc<str>),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Float(_) => f64::NAME,
+            Value::Int(_) => i64::NAME,
+            Value::Bool(_) => bool::NAME,
+            Value::Str(_) => String::NAME,
+        }
+    }
+
+    /// Convert to the given type, e.g. to store a value of the quantity `name`.
+    pub fn typed<V: ValueType>(&self, name: &'static str) -> Result<V, Error> {
+        V::from_value(self).ok_or_else(|| Error::UnexpectedValueType {
+            name,
+            expected: V::NAME,
+            actual: self.type_name(),
+        })
+    }
+
+    /// Whether the values are equal, floats within the tolerance (two NaNs are equal).
+    pub fn approx_eq(&self, other: &Value, epsilon: f64) -> bool {
+        match (self, other) {
+            (Value::Float(a), Value::Float(b)) => {
+                a == b || (a - b).abs() <= epsilon || (a.is_nan() && b.is_nan())
+            }
+            _ => self == other,
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Float(v) => write!(f, "{}", v),
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Bool(v) => write!(f, "{}", v),
+            Value::Str(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Rust type of a quantity or a `#[tem_fn]` result.
+///
+/// Implement it for categorical enums by converting them to and from [`Value::Str`].
+pub trait ValueType: Sized {
+    /// Name of the type in error messages.
+    const NAME: &'static str;
+
+    fn into_value(self) -> Value;
+
+    /// `None` if the value has a different type.
+    fn from_value(value: &Value) -> Option<Self>;
+
+    /// Returned by a function whose evaluation was aborted, e.g. by a cyclic dependency.
+    /// The error is reported by the enclosing evaluation, so the placeholder is never
+    /// seen by its caller.
+    fn placeholder() -> Self;
+}
+
+impl ValueType for f64 {
+    const NAME: &'static str = "f64";
+
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn placeholder() -> Self {
+        f64::NAN
+    }
+}
+
+impl ValueType for i64 {
+    const NAME: &'static str = "i64";
+
+    fn into_value(self) -> Value {
+        Value::Int(self)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn placeholder() -> Self {
+        0
+    }
+}
+
+impl ValueType for bool {
+    const NAME: &'static str = "bool";
+
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn placeholder() -> Self {
+        false
+    }
+}
+
+impl ValueType for String {
+    const NAME: &'static str = "String";
+
+    fn into_value(self) -> Value {
+        Value::Str(self.into())
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Str(v) => Some(v.to_string()),
+            _ => None,
+        }
+    }
+
+    fn placeholder() -> Self {
+        String::new()
+    }
+}
+
+impl ValueType for Arc<str> {
+    const NAME: &'static str = "String";
+
+    fn into_value(self) -> Value {
+        Value::Str(self)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Str(v) => Some(v.clone()),
+            _ => None,
+        }
+    }
+
+    fn placeholder() -> Self {
+        "".into()
+    }
+}
+
+impl ValueType for Value {
+    const NAME: &'static str = "Value";
+
+    fn into_value(self) -> Value {
+        self
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+
+    fn placeholder() -> Self {
+        Value::Float(f64::NAN)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(value.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Str(value.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_round_trip() {
+        assert_eq!(f64::from_value(&1.5.into_value()), Some(1.5));
+        assert_eq!(i64::from_value(&Value::Int(3)), Some(3));
+        assert_eq!(bool::from_value(&Value::Int(1)), None);
+        assert_eq!(String::from_value(&"coal".into()), Some("coal".to_string()));
+        assert_eq!(Value::Bool(true).type_name(), "bool");
+    }
+
+    #[test]
+    fn floats_are_compared_within_tolerance() {
+        assert!(Value::Float(1.0).approx_eq(&Value::Float(1.0 + 1e-12), 1e-9));
+        assert!(Value::Float(f64::NAN).approx_eq(&Value::Float(f64::NAN), 0.0));
+        assert!(!Value::Int(1).approx_eq(&Value::Float(1.0), 1e-9));
+    }
+}
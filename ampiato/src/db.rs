@@ -2,28 +2,192 @@ use std::{
     cell::RefCell,
     collections::{HashMap, HashSet},
     hash::Hash,
-    rc::Rc,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
 };
 
 use crate::replication::{
-    from_tuple_data::TableFromTupleData, pgoutput::LogicalReplicationMessage,
-    replication::Replication,
+    from_tuple_data::{TableFromTupleData, TupleRow},
+    pgoutput::{KeyOrOldTupleData, LogicalReplicationMessage},
+    relation::{Relation, RelationCache},
+    replication::{format_lsn, Replication, ReplicationConfig},
 };
 use crate::{
     core::{
-        defs::{Index, Time},
+        defs::{Index, Time, TimeRange},
+        value::{Value, ValueType},
         TableValues,
     },
     Error,
 };
 use colored::Colorize as _;
-use petgraph::matrix_graph::{MatrixGraph, NodeIndex};
+use rayon::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
 use sqlx::PgPool;
 
+use super::explain::Explanation;
+use super::graph::{node_label, DepGraph, GraphScope, NodeT};
+use super::scenario::Scenario;
+use super::snapshot::{read_snapshot, write_snapshot};
+use super::ts::{TimeSeriesChanges, TimeSeriesDense};
 use super::value_provider::ValueProvider;
 
-pub type NodeT<Sel> = (&'static str, Sel, Time);
-pub type DepGraph<Sel> = MatrixGraph<NodeT<Sel>, (), petgraph::Directed, Option<()>, usize>;
+pub type SubscriptionId = usize;
+
+type ValueFn<Sel, T, VP> = Arc<dyn Fn(&Db<Sel, T, VP>) -> Value + Send + Sync>;
+type Refs<Sel> = HashMap<NodeT<Sel>, Index>;
+type TryValueFn<Sel, T, VP> = Arc<dyn Fn(&Db<Sel, T, VP>) -> Result<Value, Error> + Send + Sync>;
+type ValueCache<Sel> = HashMap<NodeT<Sel>, Result<Value, Error>>;
+type ValueFns<Sel, T, VP> = HashMap<NodeT<Sel>, TryValueFn<Sel, T, VP>>;
+/// Changes of the values by the time they became known, see [`Db::enable_history`].
+type History<Sel> = HashMap<NodeT<Sel>, TimeSeriesChanges<Option<Value>>>;
+/// Invalidated nodes with their dropped cache entries.
+type Invalidated<Sel> = HashMap<Index, Option<(NodeT<Sel>, Result<Value, Error>)>>;
+type Subscribers<Sel, T, VP> = HashMap<SubscriptionId, Subscriber<Sel, T, VP>>;
+
+struct Subscriber<Sel, T, VP>
+where
+    Sel: Clone + Eq + Hash,
+    T: TableFromTupleData + TableValues<Sel>,
+    VP: ValueProvider<Sel>,
+{
+    value_fn: ValueFn<Sel, T, VP>,
+    /// Nodes read by the last evaluation of `value_fn`.
+    refs: HashSet<Index>,
+    value: Value,
+}
+
+/// Values overridden by a scenario, see [`Db::scenario`].
+struct Overlay<Sel> {
+    values: HashMap<NodeT<Sel>, Value>,
+    /// Cache of the live database, still valid for the nodes not affected by the overrides.
+    base_cache: Arc<RwLock<ValueCache<Sel>>>,
+    /// Knowledge time of the values that are not overridden, `None` for the latest.
+    as_of: Option<Time>,
+    /// Nodes whose value may differ from the live database.
+    affected: HashSet<Index>,
+}
+
+/// Change of a single value.
+enum ValueChange {
+    Set(Value),
+    /// Explicitly cleared, e.g. replicated as NULL.
+    Clear,
+    /// Removed, e.g. with its deleted row.
+    Remove,
+}
+
+/// Decoded change of a replicated transaction.
+enum RowChange<Sel> {
+    /// Values of a row, at its selector and time.
+    Values(Sel, Time, Vec<(&'static str, ValueChange)>),
+    /// All the rows of the table removed.
+    Truncate(String),
+}
+
+/// Dependency tracing state of one evaluation task, e.g. a call made by the user or one of
+/// the calls of [`Db::eval_many`].
+struct DepTracing {
+    /// Database evaluating the task, see `Db::id`.
+    db_id: usize,
+    /// Function nodes being evaluated by the tasks that started this one, outermost
+    /// first, e.g. the caller of [`Db::eval_many`].
+    callers: Vec<Index>,
+    frames: Vec<DepTracingFrame>,
+    /// Error that aborted the evaluation, reported by the nearest frame that catches it.
+    error: Option<Error>,
+}
+
+thread_local! {
+    /// Tasks being evaluated on this thread, innermost last.
+    ///
+    /// A thread waiting for a rayon task may run another one in the meantime, which is
+    /// traced in its own context on top of the waiting one.
+    static DEP_TRACING: RefCell<Vec<DepTracing>> = const { RefCell::new(Vec::new()) };
+}
+
+static NEXT_DB_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Context of a task started by `Db::dep_tracing_task`, removed even if the task panics.
+struct DepTracingTask;
+
+impl DepTracingTask {
+    fn finish(self) -> DepTracing {
+        std::mem::forget(self);
+        DEP_TRACING.with_borrow_mut(|contexts| contexts.pop().unwrap())
+    }
+}
+
+impl Drop for DepTracingTask {
+    fn drop(&mut self) {
+        DEP_TRACING.with_borrow_mut(|contexts| contexts.pop());
+    }
+}
+
+/// Frame pushed by `Db::dep_tracing_function_call`, popped even if the evaluation panics,
+/// so that the frames of a panicking function are not left behind on a thread that is
+/// reused, e.g. by a tokio worker.
+struct DepTracingCall {
+    db_id: usize,
+}
+
+impl Drop for DepTracingCall {
+    fn drop(&mut self) {
+        DEP_TRACING.with_borrow_mut(|contexts| {
+            let Some(position) = contexts.iter().rposition(|c| c.db_id == self.db_id) else {
+                return;
+            };
+            let context = &mut contexts[position];
+            context.frames.pop();
+            if context.frames.is_empty() {
+                contexts.remove(position);
+            }
+        });
+    }
+}
+
+struct DepTracingFrame {
+    /// Function node being evaluated, if any.
+    node: Option<Index>,
+    /// Nodes read by the evaluation.
+    deps: HashSet<Index>,
+    /// Whether the errors aborting the evaluation stop at this frame, see [`Db::try_eval`].
+    catches_error: bool,
+}
+
+/// Handle to a subscription created by [`Db::subscribe`].
+///
+/// Dropping the handle unsubscribes. The function results computed only for the
+/// subscription are dropped from the cache, and the parts of the dependency graph that are
+/// no longer needed by any other subscription or cached value are pruned, by the next
+/// update of the database or right away by [`Db::unsubscribe`].
+pub struct Subscription {
+    id: SubscriptionId,
+    unsubscribe: Option<Box<dyn FnOnce(SubscriptionId) + Send + Sync>>,
+}
+
+impl Subscription {
+    pub fn id(&self) -> SubscriptionId {
+        self.id
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(unsubscribe) = self.unsubscribe.take() {
+            unsubscribe(self.id);
+        }
+    }
+}
+
+impl std::fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscription").field("id", &self.id).finish()
+    }
+}
 
 pub struct Db<Sel, T, VP>
 where
@@ -31,23 +195,50 @@ where
     T: TableFromTupleData + TableValues<Sel>,
     VP: ValueProvider<Sel>,
 {
-    value_provider: VP,
-    refs: Rc<RefCell<HashMap<NodeT<Sel>, NodeIndex<usize>>>>,
-    deps: Rc<RefCell<DepGraph<Sel>>>,
+    /// Shared with the scenarios, which borrow the database, so it is never shared while
+    /// the database is mutated.
+    value_provider: Arc<VP>,
+    /// Overridden values, if this is a scenario.
+    overlay: Option<Overlay<Sel>>,
+    /// Past values, if the history is enabled. Shared with the scenarios like the value
+    /// provider.
+    history: Option<Arc<History<Sel>>>,
+    refs: Arc<RwLock<Refs<Sel>>>,
+    deps: Arc<RwLock<DepGraph<Sel>>>,
 
-    dep_tracing_stack: Rc<RefCell<Vec<HashSet<Index>>>>,
+    /// Identifies the dependency tracing contexts of this database, see `DEP_TRACING`.
+    id: usize,
 
-    replication: Rc<RefCell<Option<Replication>>>,
+    replication: tokio::sync::Mutex<Option<Replication>>,
 
-    subs: HashMap<Index, NodeT<Sel>>,
-    // value_cache: HashMap<NodeT<Sel>, f64>,
+    subs: Arc<RwLock<Subscribers<Sel, T, VP>>>,
+    next_subscription_id: SubscriptionId,
+    needs_pruning: Arc<AtomicBool>,
+    /// Nodes read by the subscriptions dropped since the last pruning.
+    released_refs: Arc<Mutex<Vec<Index>>>,
+    value_cache: Arc<RwLock<ValueCache<Sel>>>,
+    /// Closures of the evaluated function nodes, used to re-evaluate them on updates.
+    value_fns: Arc<RwLock<ValueFns<Sel, T, VP>>>,
+    /// End LSN of the last applied replicated transaction.
+    last_lsn: Option<u64>,
+    /// End LSN of the last snapshot saved or loaded. A persistent slot is confirmed only
+    /// up to it, so that the changes after it can be replicated again after a restart.
+    snapshot_lsn: RwLock<Option<u64>>,
+    /// Subscription updates of the transactions applied by a call of
+    /// [`Db::apply_changes`] that failed afterwards, returned by the next call.
+    unreported_updates: Vec<(SubscriptionId, Value, Value)>,
+    /// Relations described in the replicated changes.
+    relations: RelationCache,
+    /// Tolerance of the early-cutoff invalidation, `None` when disabled.
+    early_cutoff: Option<f64>,
+    /// Values the cached dependents of the nodes were computed from, kept while early
+    /// cutoff hides the changes of the nodes, so that small changes do not add up.
+    cutoff_baselines: HashMap<NodeT<Sel>, Option<Value>>,
     verbose: bool,
 
-    phantom: std::marker::PhantomData<T>,
+    phantom: std::marker::PhantomData<fn() -> T>,
 }
 
-// type SubsciptionId = usize;
-
 impl<Sel, T, VP> Db<Sel, T, VP>
 where
     Sel: Clone + Eq + Hash,
@@ -75,47 +266,288 @@ where
     pub async fn from_pool(pool: &PgPool, replication: bool) -> Result<Self, sqlx::Error> {
         let vp = VP::from_pool(pool).await;
 
-        let replication = if replication {
-            Some(Replication::from_pool(pool).await?)
-        } else {
-            None
-        };
+        let db = Self::from_value_provider(vp);
+        if replication {
+            *db.replication.lock().await = Some(Replication::from_pool(pool).await?);
+        }
+        Ok(db)
+    }
 
-        Ok(Db {
-            value_provider: vp,
-            refs: Rc::new(RefCell::new(HashMap::new())),
-            deps: Rc::new(RefCell::new(DepGraph::default())),
-            dep_tracing_stack: Rc::new(RefCell::new(Vec::new())),
-            replication: Rc::new(RefCell::new(replication)),
-            subs: HashMap::new(),
-            // value_cache: HashMap::new(),
+    /// Create a database over an already loaded value provider, without replication.
+    pub fn from_value_provider(value_provider: VP) -> Self {
+        Db {
+            value_provider: Arc::new(value_provider),
+            overlay: None,
+            history: None,
+            refs: Arc::new(RwLock::new(HashMap::new())),
+            deps: Arc::new(RwLock::new(DepGraph::default())),
+            id: NEXT_DB_ID.fetch_add(1, Ordering::Relaxed),
+            replication: tokio::sync::Mutex::new(None),
+            subs: Arc::new(RwLock::new(HashMap::new())),
+            next_subscription_id: 0,
+            needs_pruning: Arc::new(AtomicBool::new(false)),
+            released_refs: Arc::new(Mutex::new(Vec::new())),
+            value_cache: Arc::new(RwLock::new(HashMap::new())),
+            value_fns: Arc::new(RwLock::new(HashMap::new())),
+            last_lsn: None,
+            snapshot_lsn: RwLock::new(None),
+            unreported_updates: Vec::new(),
+            relations: RelationCache::default(),
+            early_cutoff: None,
+            cutoff_baselines: HashMap::new(),
             verbose: false,
             phantom: std::marker::PhantomData,
-        })
+        }
+    }
+
+    /// Create a database from a snapshot written by [`Db::save_snapshot`], without
+    /// replication.
+    ///
+    /// Loading a snapshot is much faster than loading all the tables from the database.
+    /// The changes committed after the snapshot was taken, i.e. after [`Db::last_lsn`],
+    /// have to be replicated from a persistent replication slot, which keeps them until a
+    /// newer snapshot is saved.
+    pub fn from_snapshot(path: impl AsRef<Path>) -> Result<Self, Error>
+    where
+        VP: DeserializeOwned,
+    {
+        let snapshot = read_snapshot(path.as_ref())?;
+        let mut db = Self::from_value_provider(snapshot.value_provider);
+        db.last_lsn = snapshot.lsn;
+        db.snapshot_lsn = RwLock::new(snapshot.lsn);
+        Ok(db)
+    }
+
+    /// Write the values of the value provider and [`Db::last_lsn`] to a snapshot file.
+    ///
+    /// The dependency graph and the cached values are not saved; they are rebuilt on
+    /// demand after the restart. Once the snapshot is on disk, the next
+    /// [`Db::confirm_changes`] lets a persistent slot release the changes it contains.
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> Result<(), Error>
+    where
+        VP: Serialize,
+    {
+        write_snapshot(path.as_ref(), self.last_lsn, &*self.value_provider)?;
+        *self.snapshot_lsn.write().unwrap() = self.last_lsn;
+        Ok(())
+    }
+
+    /// End LSN of the last replicated transaction applied to the database.
+    pub fn last_lsn(&self) -> Option<u64> {
+        self.last_lsn
+    }
+
+    /// End LSN of the last transaction in the snapshot saved or loaded, see
+    /// [`Db::save_snapshot`]. A persistent slot is confirmed only up to it, so that the
+    /// later changes are replicated again after a restart.
+    pub fn snapshot_lsn(&self) -> Option<u64> {
+        *self.snapshot_lsn.read().unwrap()
+    }
+
+    /// Start a what-if scenario on top of this database, see [`Scenario`].
+    pub fn scenario(&self) -> Scenario<'_, Sel, T, VP> {
+        Scenario::new(self, None)
+    }
+
+    /// Keep every change of the values together with the time it became known, so that
+    /// functions can be evaluated as of a past moment with [`Db::as_of`].
+    ///
+    /// Replicated changes are known at the commit time of their transaction, direct
+    /// updates at the time of the call (see [`Db::update_many_known_at`]). The values
+    /// loaded at startup are known since ever. The history is kept in memory and grows
+    /// with every change.
+    pub fn enable_history(&mut self) {
+        self.history.get_or_insert_with(Default::default);
+    }
+
+    /// Evaluate functions as they would have been computed at the given commit time, e.g.
+    /// `pMax(&db.as_of(t_commit), b, t)`.
+    ///
+    /// Returns a [`Scenario`] seeing the values known at that time, which can be
+    /// overridden further. Panics if the history is not enabled, see
+    /// [`Db::enable_history`].
+    pub fn as_of(&self, knowledge_time: Time) -> Scenario<'_, Sel, T, VP> {
+        assert!(self.history.is_some(), "History is not enabled.");
+        Scenario::new(self, Some(knowledge_time))
+    }
+
+    /// Database evaluating the functions of a scenario.
+    ///
+    /// It shares the value provider, the dependency graph and the function closures with
+    /// this database, but has its own cache, overlay of overridden values and
+    /// subscriptions. It never prunes the shared graph.
+    pub(crate) fn scenario_db(&self, as_of: Option<Time>) -> Self {
+        // The cached results of the live database are based on the latest values.
+        let base_cache = match as_of {
+            None => self.value_cache.clone(),
+            Some(_) => Arc::new(RwLock::new(HashMap::new())),
+        };
+        Db {
+            value_provider: self.value_provider.clone(),
+            overlay: Some(Overlay {
+                values: HashMap::new(),
+                base_cache,
+                as_of,
+                affected: HashSet::new(),
+            }),
+            history: self.history.clone(),
+            refs: self.refs.clone(),
+            deps: self.deps.clone(),
+            id: NEXT_DB_ID.fetch_add(1, Ordering::Relaxed),
+            replication: tokio::sync::Mutex::new(None),
+            subs: Arc::new(RwLock::new(HashMap::new())),
+            next_subscription_id: 0,
+            needs_pruning: Arc::new(AtomicBool::new(false)),
+            released_refs: Arc::new(Mutex::new(Vec::new())),
+            value_cache: Arc::new(RwLock::new(HashMap::new())),
+            value_fns: self.value_fns.clone(),
+            last_lsn: self.last_lsn,
+            snapshot_lsn: RwLock::new(None),
+            unreported_updates: Vec::new(),
+            relations: RelationCache::default(),
+            early_cutoff: self.early_cutoff,
+            cutoff_baselines: self.cutoff_baselines.clone(),
+            verbose: self.verbose,
+            phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Values overridden by a scenario, in no particular order.
+    pub(crate) fn take_overrides(&mut self) -> Vec<(&'static str, Sel, Time, Value)> {
+        match self.overlay.as_mut() {
+            Some(overlay) => std::mem::take(&mut overlay.values)
+                .into_iter()
+                .map(|((name, selector, t), value)| (name, selector, t, value))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub(crate) fn needs_pruning(&self) -> &Arc<AtomicBool> {
+        &self.needs_pruning
+    }
+
+    /// Enable early-cutoff invalidation with the given tolerance, or disable it with `None`.
+    ///
+    /// By default, an update drops the cached values of all transitive dependents of the
+    /// changed values. With early cutoff, the cached dependents are re-evaluated bottom-up
+    /// instead, and the propagation stops at nodes whose new value differs from the cached
+    /// one by at most `epsilon`. Subscriptions whose value did not change are not reported.
+    ///
+    /// Changes are compared with the values the cached dependents were computed from, so
+    /// small changes in a row are propagated once they add up to more than `epsilon`.
+    pub fn set_early_cutoff(&mut self, epsilon: Option<f64>) {
+        self.early_cutoff = epsilon;
+    }
+
+    /// Start replicating the changes from the slot and publication of the config, e.g.
+    /// after [`Db::from_snapshot`].
+    ///
+    /// A persistent slot that already exists is reused, so the changes committed since
+    /// they were last confirmed are replicated. Fails if the slot has already confirmed
+    /// changes newer than [`Db::last_lsn`], which the database would miss.
+    pub async fn start_replication(
+        &self,
+        pool: &PgPool,
+        config: ReplicationConfig,
+    ) -> Result<(), Error> {
+        let replication = Replication::from_pool_with_config(pool, config).await?;
+        let confirmed_lsn = replication.confirmed_lsn();
+        if let (Some(last_lsn), Some(confirmed_lsn)) = (self.last_lsn, confirmed_lsn) {
+            if last_lsn < confirmed_lsn {
+                return Err(Error::ReplicationError(format!(
+                    "The database is at {}, but the slot has already confirmed changes up to {}",
+                    format_lsn(last_lsn),
+                    format_lsn(confirmed_lsn)
+                )));
+            }
+        }
+        *self.replication.lock().await = Some(replication);
+        Ok(())
     }
 
     pub async fn stop_replication(&self) -> Result<(), Error> {
-        let mut replication = self.replication.borrow_mut();
-        if let Some(replication) = replication.as_mut() {
+        if let Some(replication) = self.replication.lock().await.as_mut() {
             replication.close_and_cleanup().await?;
         }
         Ok(())
     }
 
-    pub async fn sync_changes(&mut self) -> Result<HashSet<Index>, Error> {
-        let changes = {
-            let mut replication = self.replication.borrow_mut();
-            let replication = replication.as_mut();
-            if let Some(replication) = replication {
-                replication.grab_changes().await?
-            } else {
-                return Err(Error::ReplicationNotEnabled);
+    /// Apply the changes replicated since the last call and recompute the subscriptions
+    /// that depend on them.
+    ///
+    /// Returns `(subscription, old_value, new_value)` for every recomputed subscription.
+    /// The applied transactions are confirmed afterwards (see [`Db::confirm_changes`]),
+    /// even if a later transaction failed.
+    ///
+    /// This polls the slot of [`Db::start_replication`], so the changes arrive only when
+    /// it is called and each call has the server decode the changes since the confirmed
+    /// position again. It suits occasional syncs, e.g. before a batch of evaluations. To
+    /// react to the changes as they are committed, read them from a
+    /// [`ReplicationStream`](crate::ReplicationStream) instead and pass each transaction
+    /// to [`Db::apply_changes`]; the two must not share a slot.
+    pub async fn sync_changes(&mut self) -> Result<Vec<(SubscriptionId, Value, Value)>, Error> {
+        let changes = self.grab_changes().await?;
+        let result = self.apply_changes(changes);
+        self.confirm_changes().await?;
+        result
+    }
+
+    /// Confirm the transactions applied so far to the replication slot, so that they are
+    /// not replicated again.
+    ///
+    /// A persistent slot is confirmed only up to the last snapshot saved (or loaded), see
+    /// [`Db::save_snapshot`]. The later transactions are decoded again by the server on
+    /// every [`Db::grab_changes`] and skipped, until a newer snapshot is saved.
+    pub async fn confirm_changes(&self) -> Result<(), Error> {
+        if self.last_lsn.is_none() {
+            return Ok(());
+        }
+        let snapshot_lsn = *self.snapshot_lsn.read().unwrap();
+        match self.replication.lock().await.as_mut() {
+            Some(replication) => {
+                let lsn = if replication.is_temporary() {
+                    self.last_lsn
+                } else {
+                    snapshot_lsn
+                };
+                match lsn {
+                    Some(lsn) => replication.confirm(lsn).await,
+                    None => Ok(()),
+                }
             }
-        };
+            None => Err(Error::ReplicationNotEnabled),
+        }
+    }
+
+    /// Fetch the changes not confirmed yet by [`Db::confirm_changes`], without applying
+    /// them.
+    ///
+    /// The transactions up to [`Db::last_lsn`] are skipped by the server, see
+    /// [`Replication::grab_changes`].
+    pub async fn grab_changes(&self) -> Result<Vec<LogicalReplicationMessage>, Error> {
+        match self.replication.lock().await.as_mut() {
+            Some(replication) => replication.grab_changes(self.last_lsn).await,
+            None => Err(Error::ReplicationNotEnabled),
+        }
+    }
 
+    /// Apply changes fetched by [`Db::grab_changes`] and recompute the affected
+    /// subscriptions.
+    ///
+    /// Transactions up to [`Db::last_lsn`] are skipped, because they are already applied,
+    /// e.g. in the snapshot the database was loaded from.
+    ///
+    /// If a transaction fails, the ones before it stay applied. Their subscriptions are
+    /// recomputed before the error is returned, and the updates are returned by the next
+    /// call.
+    pub fn apply_changes(
+        &mut self,
+        changes: Vec<LogicalReplicationMessage>,
+    ) -> Result<Vec<(SubscriptionId, Value, Value)>, Error> {
         let mut is_in_transaction = false;
         let mut transaction_messages = Vec::new();
-        let mut updated_subscribers = HashSet::new();
+        let mut dirty_refs = HashSet::new();
         for change in changes.into_iter() {
             match change {
                 LogicalReplicationMessage::Begin(_) => {
@@ -124,15 +556,48 @@ where
                     }
                     is_in_transaction = true;
                 }
-                LogicalReplicationMessage::Commit(_) => {
+                LogicalReplicationMessage::Commit(commit) => {
                     if !is_in_transaction {
                         panic!("Commit message found outside of transaction.");
                     }
                     is_in_transaction = false;
-                    let updated_ids = self.apply_transaction(&transaction_messages)?;
-                    updated_subscribers.extend(updated_ids);
+                    if self.last_lsn.is_some_and(|last_lsn| commit.end_lsn <= last_lsn) {
+                        // The relations are described only once per replication session,
+                        // so the later transactions need them even if this one is skipped.
+                        for message in transaction_messages.drain(..) {
+                            match message {
+                                LogicalReplicationMessage::Relation(r) => {
+                                    self.relations.update(&r);
+                                }
+                                LogicalReplicationMessage::Type(t) => {
+                                    self.relations.update_type(&t);
+                                }
+                                _ => {}
+                            }
+                        }
+                        continue;
+                    }
+                    let knowledge_time = Time::from_datetime(commit.commit_timestamp);
+                    let updated_refs =
+                        match self.apply_transaction(&transaction_messages, knowledge_time) {
+                            Ok(updated_refs) => updated_refs,
+                            Err(error) => {
+                                let updates = self.recompute_subscriptions(&dirty_refs);
+                                self.unreported_updates.extend(updates);
+                                return Err(error);
+                            }
+                        };
+                    self.last_lsn = Some(commit.end_lsn);
+                    dirty_refs.extend(updated_refs);
                     transaction_messages.clear();
                 }
+                // Described by a transaction skipped by `Replication::grab_changes`.
+                LogicalReplicationMessage::Relation(r) if !is_in_transaction => {
+                    self.relations.update(&r);
+                }
+                LogicalReplicationMessage::Type(t) if !is_in_transaction => {
+                    self.relations.update_type(&t);
+                }
                 _ => {
                     if !is_in_transaction {
                         panic!("Change message found outside of transaction.");
@@ -142,49 +607,162 @@ where
             }
         }
 
-        Ok(updated_subscribers)
+        let mut updates = std::mem::take(&mut self.unreported_updates);
+        updates.extend(self.recompute_subscriptions(&dirty_refs));
+        Ok(updates)
     }
 
+    /// Apply the changes of a transaction in order, then invalidate their dependents.
+    ///
+    /// All the rows are decoded before any value is changed, so that a transaction that
+    /// fails to decode leaves the database as it was. A value of an unexpected type is
+    /// skipped, and the error is returned once the other values are set. The changes of the tables not in `T` are
+    /// skipped, e.g. with a publication for all tables.
+    ///
+    /// Deleted rows are decoded from their old tuple with `REPLICA IDENTITY FULL`, or from
+    /// their key, whose other columns are NULL. An updated row whose selector or time
+    /// changed is removed from its previous key first.
     fn apply_transaction(
         &mut self,
         messages: &[LogicalReplicationMessage],
+        knowledge_time: Time,
     ) -> Result<HashSet<Index>, Error> {
         if self.verbose {
             Self::describe_transacrtion(messages);
         }
-        let mut relation_name = "";
-        let mut updated_subscribers = HashSet::new();
+
+        let table_names = T::table_names();
+        let is_replicated = |relation: &Relation| table_names.contains(&relation.name.as_str());
+        let mut row_changes = Vec::new();
         for message in messages.iter() {
-            match message {
+            let (relation_oid, tuple_data, old_tuple, previous_key, is_delete) = match message {
                 LogicalReplicationMessage::Relation(r) => {
-                    relation_name = &r.relation_name;
+                    self.relations.update(r);
+                    continue;
+                }
+                LogicalReplicationMessage::Type(t) => {
+                    self.relations.update_type(t);
+                    continue;
                 }
                 LogicalReplicationMessage::Update(u) => {
-                    let table = T::from_tuple_data(relation_name, &u.new_tuple)?;
-                    let t = table.time();
-                    let sel = table.selector();
-                    let values = table.values();
-                    for (name, value) in values.iter() {
-                        let updated_ids = self.update(name, &sel, &t, **value);
-                        updated_subscribers.extend(updated_ids);
-                    }
+                    let old_tuple = match &u.key_or_old_tuple {
+                        Some(KeyOrOldTupleData::Old(old_tuple)) => Some(old_tuple),
+                        _ => None,
+                    };
+                    // Replicated only if the key changed or with `REPLICA IDENTITY FULL`.
+                    let previous_key = match &u.key_or_old_tuple {
+                        Some(KeyOrOldTupleData::Key(tuple) | KeyOrOldTupleData::Old(tuple)) => {
+                            Some(tuple)
+                        }
+                        None => None,
+                    };
+                    (u.relation_oid, &u.new_tuple, old_tuple, previous_key, false)
                 }
                 LogicalReplicationMessage::Insert(i) => {
-                    let table = T::from_tuple_data(relation_name, &i.new_tuple)?;
-                    let t = table.time();
-                    let sel = table.selector();
-                    let values = table.values();
-                    for (name, value) in values.iter() {
-                        let updated_ids = self.update(name, &sel, &t, **value);
-                        updated_subscribers.extend(updated_ids);
+                    (i.relation_oid, &i.new_tuple, None, None, false)
+                }
+                LogicalReplicationMessage::Delete(d) => match &d.key_or_old_tuple {
+                    Some(KeyOrOldTupleData::Key(tuple) | KeyOrOldTupleData::Old(tuple)) => {
+                        (d.relation_oid, tuple, None, None, true)
+                    }
+                    None => {
+                        let relation = self.relations.get(d.relation_oid)?;
+                        if !is_replicated(relation) {
+                            continue;
+                        }
+                        return Err(Error::ReplicationError(format!(
+                            "Deleted row of {} has neither key nor old tuple",
+                            relation.name
+                        )));
+                    }
+                },
+                LogicalReplicationMessage::Truncate(truncate) => {
+                    for relation_oid in truncate.relation_oids.iter() {
+                        let relation = self.relations.get(*relation_oid)?;
+                        if is_replicated(relation) {
+                            row_changes.push(RowChange::Truncate(relation.name.clone()));
+                        }
                     }
+                    continue;
                 }
                 _ => {
                     panic!("Unsupported message type: {:?}", message);
                 }
+            };
+            let relation = self.relations.get(relation_oid)?;
+            if !is_replicated(relation) {
+                continue;
+            }
+            let row = TupleRow::new(relation, tuple_data, old_tuple)?;
+            let table = T::from_tuple_data(&row)?;
+            if let Some(previous_key) = previous_key {
+                let previous = T::from_tuple_data(&TupleRow::new(relation, previous_key, None)?)?;
+                if previous.selector() != table.selector() || previous.time() != table.time() {
+                    let values = previous
+                        .values()
+                        .into_iter()
+                        .map(|(name, _)| (name, ValueChange::Remove))
+                        .collect();
+                    row_changes.push(RowChange::Values(
+                        previous.selector(),
+                        previous.time(),
+                        values,
+                    ));
+                }
+            }
+            // Unchanged TOAST values that are not replicated keep their current value.
+            let values = table
+                .values()
+                .into_iter()
+                .filter(|(name, _)| {
+                    let source = self.value_provider.quantity_source(name);
+                    !source.is_some_and(|(_, column)| row.is_unchanged(column))
+                })
+                .map(|(name, value)| {
+                    let change = match value {
+                        _ if is_delete => ValueChange::Remove,
+                        Some(value) => ValueChange::Set(value),
+                        None => ValueChange::Clear,
+                    };
+                    (name, change)
+                })
+                .collect();
+            row_changes.push(RowChange::Values(table.selector(), table.time(), values));
+        }
+
+        // Only values of an unexpected type fail from here on. They are skipped, and the
+        // error is returned once the rest of the transaction is applied.
+        self.prune_graph();
+        let mut changed_refs = Vec::new();
+        let mut error = None;
+        for row_change in row_changes {
+            match row_change {
+                RowChange::Values(sel, t, values) => {
+                    for (name, change) in values {
+                        match self.set_value_known_at(
+                            name,
+                            sel.clone(),
+                            t,
+                            change,
+                            knowledge_time,
+                        ) {
+                            Ok(changed_ref) => changed_refs.extend(changed_ref),
+                            Err(e) => {
+                                error.get_or_insert(e);
+                            }
+                        }
+                    }
+                }
+                RowChange::Truncate(table_name) => {
+                    changed_refs.extend(self.remove_table_known_at(&table_name, knowledge_time));
+                }
             }
         }
-        Ok(updated_subscribers)
+
+        // The whole transaction is applied first, so that the dependency graph is
+        // traversed only once.
+        let dirty_refs = self.invalidate_changed(changed_refs);
+        self.dirty_refs_or_error(dirty_refs, error)
     }
 
     fn describe_transacrtion(messages: &[LogicalReplicationMessage]) {
@@ -199,138 +777,2234 @@ where
 
     fn get_ref_for_value(&self, name: &'static str, selector: Sel, t: Time) -> Index {
         let key = (name, selector, t);
-        {
-            let refs = (*self.refs).borrow();
-            if let Some(&node_index) = refs.get(&key) {
-                return node_index;
-            }
+        if let Some(&node_index) = self.refs.read().unwrap().get(&key) {
+            return node_index;
         }
 
-        let mut deps = (*self.deps).borrow_mut();
-        let index = deps.add_node(key.clone());
+        // Another thread may have added the node in the meantime.
+        let mut refs = self.refs.write().unwrap();
+        if let Some(&node_index) = refs.get(&key) {
+            return node_index;
+        }
 
-        (*self.refs).borrow_mut().insert(key, index);
+        let index = self.deps.write().unwrap().add_node(key.clone());
+        refs.insert(key, index);
 
         index
     }
 
     pub fn get_value(&self, name: &'static str, selector: Sel, t: Time) -> f64 {
-        let v: f64 = self.value_provider.get_value(name, &selector, &t);
+        self.get_value_as(name, selector, t)
+    }
+
+    /// Read a value of any type, e.g. `db.get_value_as::<bool>("BlokAvailable", sel, t)`.
+    ///
+    /// Panics if the value is missing or has a different type.
+    pub fn get_value_as<V: ValueType>(&self, name: &'static str, selector: Sel, t: Time) -> V {
+        let v = match self.overlay_value(name, &selector, &t) {
+            Some(v) => v.unwrap_or_else(|| panic!("Missing value of {} in the scenario", name)),
+            None => self.value_provider.get_value(name, &selector, &t),
+        };
         let r#ref = self.get_ref_for_value(name, selector, t);
         self.dep_tracing_add_dep(r#ref);
-        v
+        v.typed(name).unwrap_or_else(|error| panic!("{}", error))
     }
 
     pub fn get_value_opt(&self, name: &'static str, selector: Sel, t: Time) -> Option<f64> {
-        let v = self.value_provider.get_value_opt(name, &selector, &t);
+        let v = self.lookup_value(name, &selector, &t);
         let r#ref = self.get_ref_for_value(name, selector, t);
         self.dep_tracing_add_dep(r#ref);
-        v
+        v.map(|v| v.typed(name).unwrap_or_else(|error| panic!("{}", error)))
+    }
+
+    /// Value overridden by a scenario or known at its knowledge time, `Some(None)` if the
+    /// value was not known yet. `None` if the latest value applies.
+    fn overlay_value(
+        &self,
+        name: &'static str,
+        selector: &Sel,
+        t: &Time,
+    ) -> Option<Option<Value>> {
+        let overlay = self.overlay.as_ref()?;
+        let key = (name, selector.clone(), *t);
+        if let Some(value) = overlay.values.get(&key) {
+            return Some(Some(value.clone()));
+        }
+        let as_of = overlay.as_of?;
+        let changes = self.history.as_ref()?.get(&key)?;
+        Some(changes.value_at(&as_of).flatten())
+    }
+
+    /// Value from the overlay of a scenario, or from the value provider.
+    fn lookup_value(&self, name: &'static str, selector: &Sel, t: &Time) -> Option<Value> {
+        match self.overlay_value(name, selector, t) {
+            Some(value) => value,
+            None => self.value_provider.get_value_opt(name, selector, t),
+        }
+    }
+
+    /// Same as [`Db::get_value`], but a missing value is returned as
+    /// [`Error::MissingValue`] instead of panicking.
+    ///
+    /// The dependency is recorded either way, so that the error is invalidated once the
+    /// value is inserted.
+    pub fn try_get_value(&self, name: &'static str, selector: Sel, t: Time) -> Result<f64, Error>
+    where
+        Sel: std::fmt::Debug,
+    {
+        self.try_get_value_as(name, selector, t)
     }
 
-    pub fn register_fn(
+    /// Same as [`Db::get_value_as`], but errors are returned instead of panicking.
+    pub fn try_get_value_as<V: ValueType>(
         &self,
         name: &'static str,
         selector: Sel,
         t: Time,
-        value_fn: impl Fn(&Db<Sel, T, VP>) -> f64,
-    ) -> f64 {
-        let r#ref = self.get_ref_for_value(name, selector, t);
+    ) -> Result<V, Error>
+    where
+        Sel: std::fmt::Debug,
+    {
+        let v = self.lookup_value(name, &selector, &t);
+        let r#ref = self.get_ref_for_value(name, selector.clone(), t);
+        self.dep_tracing_add_dep(r#ref);
+        match v {
+            Some(v) => v.typed(name),
+            None => Err(Error::MissingValue {
+                name,
+                selector: format!("{:?}", selector),
+                time: t,
+                call_chain: self.dep_tracing_call_chain(),
+            }),
+        }
+    }
 
-        // TODO: Load value from the cache first.
-        self.dep_tracing_function_call();
-        let value = value_fn(self);
-        let fn_refs = self.dep_tracing_function_return();
-        std::mem::drop(value_fn);
+    /// Evaluate a function node, or return its cached value.
+    ///
+    /// The node is registered as a dependency of the calling function (if any), so that
+    /// invalidation propagates through nested function calls even when the value comes
+    /// from the cache. The closure is kept to re-evaluate the node when early cutoff is
+    /// enabled, see [`Db::set_early_cutoff`].
+    ///
+    /// A function that depends on its own value (for the same selector and time) aborts the
+    /// evaluation with [`Error::CyclicDependency`]. The error is returned by the enclosing
+    /// [`Db::try_eval`]; without one, it panics.
+    pub fn register_fn<V: ValueType + 'static>(
+        &self,
+        name: &'static str,
+        selector: Sel,
+        t: Time,
+        value_fn: impl Fn(&Db<Sel, T, VP>) -> V + Send + Sync + 'static,
+    ) -> V
+    where
+        Sel: std::fmt::Debug + 'static,
+        T: 'static,
+        VP: 'static,
+    {
+        let key = (name, selector, t);
+        let r#ref = self.get_ref_for_value(key.0, key.1.clone(), key.2);
+        self.dep_tracing_add_dep(r#ref);
 
-        {
-            let mut deps = (*self.deps).borrow_mut();
-            for dep in fn_refs {
-                deps.update_edge(dep, r#ref, ());
-            }
+        if let Some(result) = self.cached_result(&key, r#ref) {
+            return result
+                .and_then(|value| value.typed(name))
+                .unwrap_or_else(|error| panic!("{}", error));
+        }
+
+        if let Some(error) = self.cyclic_dependency_error(r#ref) {
+            self.dep_tracing_fail(error);
+            return V::placeholder();
         }
 
-        value
+        let value_fn: TryValueFn<Sel, T, VP> = Arc::new(move |db| Ok(value_fn(db).into_value()));
+        match self.evaluate_fn(r#ref, key.clone(), &*value_fn, false) {
+            Some(result) => {
+                self.store_value_fn(key, value_fn);
+                result
+                    .and_then(|value| value.typed(name))
+                    .unwrap_or_else(|_| V::placeholder())
+            }
+            // Nothing is cached, the error is reported by an enclosing evaluation.
+            None => V::placeholder(),
+        }
     }
 
-    fn dep_tracing_function_call(&self) {
-        let mut stack = self.dep_tracing_stack.borrow_mut();
-        stack.push(HashSet::new());
+    /// Same as [`Db::register_fn`], for functions that can fail, e.g. with
+    /// [`Error::MissingValue`].
+    ///
+    /// Errors are cached and invalidated like values. A cyclic dependency aborts the
+    /// evaluation like in [`Db::register_fn`], without caching any of the functions on the
+    /// way, and is returned as [`Error::CyclicDependency`] by the enclosing
+    /// [`Db::try_eval`] or the outermost function.
+    pub fn try_register_fn<V: ValueType + 'static>(
+        &self,
+        name: &'static str,
+        selector: Sel,
+        t: Time,
+        value_fn: impl Fn(&Db<Sel, T, VP>) -> Result<V, Error> + Send + Sync + 'static,
+    ) -> Result<V, Error>
+    where
+        Sel: std::fmt::Debug + 'static,
+        T: 'static,
+        VP: 'static,
+    {
+        let key = (name, selector, t);
+        let r#ref = self.get_ref_for_value(key.0, key.1.clone(), key.2);
+        self.dep_tracing_add_dep(r#ref);
+
+        if let Some(result) = self.cached_result(&key, r#ref) {
+            return result.and_then(|value| value.typed(name));
+        }
+
+        if let Some(error) = self.cyclic_dependency_error(r#ref) {
+            self.dep_tracing_fail(error.clone());
+            return Err(error);
+        }
+
+        let value_fn: TryValueFn<Sel, T, VP> =
+            Arc::new(move |db| value_fn(db).map(ValueType::into_value));
+        let result = match self.evaluate_fn(r#ref, key.clone(), &*value_fn, true) {
+            Some(result) => result,
+            // The value does not matter, the enclosing evaluation is aborted.
+            None => return Err(self.dep_tracing_error()),
+        };
+        self.store_value_fn(key, value_fn);
+        result.and_then(|value| value.typed(name))
     }
 
-    fn dep_tracing_function_return(&self) -> HashSet<Index> {
-        let mut stack = self.dep_tracing_stack.borrow_mut();
-        match stack.pop() {
-            Some(deps) => deps,
-            None => unreachable!(),
+    /// Cached result of a function node. A scenario falls back to the cache of the live
+    /// database for the nodes not affected by its overrides.
+    fn cached_result(&self, key: &NodeT<Sel>, r#ref: Index) -> Option<Result<Value, Error>> {
+        if let Some(result) = self.value_cache.read().unwrap().get(key) {
+            return Some(result.clone());
+        }
+        let overlay = self.overlay.as_ref()?;
+        if overlay.affected.contains(&r#ref) {
+            return None;
         }
+        let result = overlay.base_cache.read().unwrap().get(key).cloned()?;
+
+        // The live database may have cached the node after the overrides were applied, so
+        // its inputs are checked again.
+        let deps = self.deps.read().unwrap();
+        let affected = deps
+            .cone(r#ref, petgraph::Direction::Incoming)
+            .into_iter()
+            .any(|dep| {
+                overlay.affected.contains(&dep)
+                    || deps
+                        .node_weight(dep)
+                        .is_some_and(|node| overlay.values.contains_key(node))
+            });
+        if affected {
+            return None;
+        }
+        // Invalidated by the next overrides like the results of the scenario.
+        self.value_cache.write().unwrap().insert(key.clone(), result.clone());
+        Some(result)
     }
 
-    /// Add a dependency from the current node to the given node.
+    /// If the node is already being evaluated on the current thread, the error describing
+    /// the cycle.
+    fn cyclic_dependency_error(&self, r#ref: Index) -> Option<Error>
+    where
+        Sel: std::fmt::Debug,
+    {
+        let cycle = self.dep_tracing_find_cycle(r#ref)?;
+        let deps = self.deps.read().unwrap();
+        let path = cycle
+            .into_iter()
+            .filter_map(|r#ref| deps.node_weight(r#ref).map(node_label))
+            .collect();
+        Some(Error::CyclicDependency { path })
+    }
+
+    fn store_value_fn(&self, key: NodeT<Sel>, value_fn: TryValueFn<Sel, T, VP>) {
+        self.value_fns
+            .write()
+            .unwrap()
+            .entry(key)
+            .or_insert(value_fn);
+    }
+
+    /// Evaluate a function node, record its dependencies and cache its result.
     ///
-    /// When the dependency tracing stack is empty, there is no function being evaluated.
-    /// It means that the user is retrieving values from the database. There is no need to
-    /// track the dependencies in this case.
-    fn dep_tracing_add_dep(&self, dep: Index) {
-        let mut stack = self.dep_tracing_stack.borrow_mut();
-        let last = match stack.last_mut() {
-            Some(last) => last,
-            None => return,
+    /// Returns `None` if the evaluation was aborted and the error is reported by an
+    /// enclosing evaluation. Nothing is cached or recorded for the aborted functions. For
+    /// the outermost function, the error is returned with `returns_error` and panics
+    /// otherwise.
+    fn evaluate_fn(
+        &self,
+        r#ref: Index,
+        key: NodeT<Sel>,
+        value_fn: &dyn Fn(&Self) -> Result<Value, Error>,
+        returns_error: bool,
+    ) -> Option<Result<Value, Error>> {
+        let call = self.dep_tracing_function_call(Some(r#ref), false);
+        let result = value_fn(self);
+        let fn_refs = match self.dep_tracing_function_return(call) {
+            Ok(fn_refs) => fn_refs,
+            Err(None) => return None,
+            Err(Some(error)) if returns_error => return Some(Err(error)),
+            Err(Some(error)) => panic!("{}", error),
         };
-        last.insert(dep);
-    }
 
-    pub fn graph(&self) -> &Rc<RefCell<DepGraph<Sel>>> {
-        &self.deps
+        {
+            let mut deps = self.deps.write().unwrap();
+            // Inputs of a previous evaluation may not have been read this time. While the
+            // graph is shared with a scenario, the other database may have cached the node
+            // with those inputs, so the edges are only added.
+            if Arc::strong_count(&self.deps) == 1 {
+                deps.clear_dependencies(r#ref);
+            }
+            for dep in fn_refs {
+                deps.update_edge(dep, r#ref);
+            }
+        }
+
+        self.value_cache.write().unwrap().insert(key, result.clone());
+
+        Some(result)
     }
 
-    /// TODO: The subscription should be a separate object that automatically unsubscribes
-    /// when it is dropped.
-    pub fn subscribe(&mut self, name: &'static str, selector: &Sel, t: &Time) -> Index {
-        let ref_ = self.get_ref_for_value(name, selector.clone(), *t);
-        self.subs.insert(ref_, (name, selector.clone(), t.clone()));
-        ref_
+    /// Evaluate independent function calls in parallel on the rayon thread pool.
+    ///
+    /// Each request is `(function, arguments, time)`, e.g. `(pMax, b, t)`. The results are
+    /// returned in the order of the requests and the dependencies of every call are
+    /// recorded in the shared graph, as if the calls were made one by one. Evaluated
+    /// inside a function or a subscription, the caller depends on all the calls.
+    pub fn eval_many<A, F, V>(&self, requests: &[(F, A, Time)]) -> Vec<V>
+    where
+        F: Fn(&Self, A, Time) -> V + Sync,
+        V: Send,
+        A: Clone + Sync,
+        Self: Sync,
+    {
+        // A call depending on one of the callers is a cycle, even on another thread.
+        let callers = self.dep_tracing_nodes();
+        let results: Vec<_> = requests
+            .par_iter()
+            .map(|(value_fn, args, t)| {
+                self.dep_tracing_task(callers.clone(), || value_fn(self, args.clone(), *t))
+            })
+            .collect();
+
+        let mut values = Vec::with_capacity(results.len());
+        for (value, deps, error) in results {
+            for dep in deps {
+                self.dep_tracing_add_dep(dep);
+            }
+            if let Some(error) = error {
+                if !self.dep_tracing_fail(error.clone()) {
+                    panic!("{}", error);
+                }
+            }
+            values.push(value);
+        }
+        values
     }
 
-    pub fn unsubscribe(&mut self, _ref: Index) {
-        todo!()
+    /// Evaluate a task in its own dependency tracing context.
+    ///
+    /// Returns the nodes read by the task outside of any function, and the error that
+    /// aborted its evaluation.
+    fn dep_tracing_task<V>(
+        &self,
+        callers: Vec<Index>,
+        task: impl FnOnce() -> V,
+    ) -> (V, HashSet<Index>, Option<Error>) {
+        DEP_TRACING.with_borrow_mut(|contexts| {
+            contexts.push(DepTracing {
+                db_id: self.id,
+                callers,
+                // Collects the reads of the task itself; it never returns, so the errors
+                // are kept in the context.
+                frames: vec![DepTracingFrame {
+                    node: None,
+                    deps: HashSet::new(),
+                    catches_error: false,
+                }],
+                error: None,
+            })
+        });
+        let guard = DepTracingTask;
+        let value = task();
+        let mut context = guard.finish();
+        let deps = context.frames.pop().map(|frame| frame.deps).unwrap_or_default();
+        (value, deps, context.error)
     }
 
-    /// TODO: When updating values in bulk (e.g., in a transaction), we can wait with the
-    /// recursive part until the end of the transaction. This way, we can avoid updating
-    /// the same values multiple times.
-    pub fn update(
-        &mut self,
-        name: &'static str,
-        selector: &Sel,
-        t: &Time,
-        new_value: f64,
-    ) -> HashSet<Index> {
-        self.value_provider
-            .set_value(name, (*selector).clone(), *t, new_value);
+    /// Evaluate a function over a range of times, e.g. the hourly profile
+    /// `db.eval_range(pMax, b, &TimeRange::new(start, end, Duration::hours(1)))`.
+    ///
+    /// Every slice of a `#[tem_fn]` is a separate node with its own dependencies, so an
+    /// update invalidates only the slices whose inputs changed and evaluating the range
+    /// again recomputes only those. Evaluated inside a function or a subscription, the
+    /// caller depends on all the slices. The slices are evaluated on the calling thread,
+    /// use [`Db::eval_many`] to spread independent profiles over threads.
+    pub fn eval_range<A, V>(
+        &self,
+        value_fn: impl Fn(&Self, A, Time) -> V,
+        args: A,
+        range: &TimeRange,
+    ) -> TimeSeriesDense<V>
+    where
+        A: Clone,
+        V: Clone,
+    {
+        range
+            .times()
+            .map(|t| (t, value_fn(self, args.clone(), t)))
+            .collect()
+    }
 
-        let mut updated_subscribers = HashSet::new();
+    /// Evaluate `value_fn`, returning the errors that aborted the evaluation (such as
+    /// [`Error::CyclicDependency`]) instead of panicking.
+    pub fn try_eval<V>(&self, value_fn: impl FnOnce(&Self) -> V) -> Result<V, Error> {
+        let call = self.dep_tracing_function_call(None, true);
+        let value = value_fn(self);
+        match self.dep_tracing_function_return(call) {
+            Ok(_) => Ok(value),
+            Err(Some(error)) => Err(error),
+            Err(None) => unreachable!(),
+        }
+    }
 
-        let refs = (*self.refs).borrow();
-        let deps = (*self.deps).borrow();
+    /// Run `f` with the innermost tracing context of this database on the current thread.
+    fn with_dep_tracing<R>(&self, f: impl FnOnce(Option<&mut DepTracing>) -> R) -> R {
+        DEP_TRACING.with_borrow_mut(|contexts| {
+            f(contexts.iter_mut().rev().find(|context| context.db_id == self.id))
+        })
+    }
 
-        let r#ref = match refs.get(&(name, selector.clone(), t.clone())) {
-            Some(&r#ref) => r#ref,
-            None => return updated_subscribers,
+    fn dep_tracing_function_call(
+        &self,
+        node: Option<Index>,
+        catches_error: bool,
+    ) -> DepTracingCall {
+        let frame = DepTracingFrame {
+            node,
+            deps: HashSet::new(),
+            catches_error,
         };
+        DEP_TRACING.with_borrow_mut(|contexts| {
+            match contexts.iter_mut().rev().find(|context| context.db_id == self.id) {
+                Some(context) => context.frames.push(frame),
+                None => contexts.push(DepTracing {
+                    db_id: self.id,
+                    callers: Vec::new(),
+                    frames: vec![frame],
+                    error: None,
+                }),
+            }
+        });
+        DepTracingCall { db_id: self.id }
+    }
 
-        let mut dirty_refs = vec![r#ref];
+    /// Pop the current frame and return the nodes it read.
+    ///
+    /// If the evaluation failed, returns `Err(Some(error))` when this frame catches the
+    /// error (or is the outermost one) and `Err(None)` otherwise.
+    fn dep_tracing_function_return(
+        &self,
+        call: DepTracingCall,
+    ) -> Result<HashSet<Index>, Option<Error>> {
+        std::mem::forget(call);
+        DEP_TRACING.with_borrow_mut(|contexts| {
+            let position = match contexts.iter().rposition(|context| context.db_id == self.id) {
+                Some(position) => position,
+                None => unreachable!(),
+            };
+            let context = &mut contexts[position];
+            let frame = match context.frames.pop() {
+                Some(frame) => frame,
+                None => unreachable!(),
+            };
+            let result = match context.error {
+                None => Ok(frame.deps),
+                Some(_) if frame.catches_error || context.frames.is_empty() => {
+                    Err(context.error.take())
+                }
+                Some(_) => Err(None),
+            };
+            if context.frames.is_empty() {
+                contexts.remove(position);
+            }
+            result
+        })
+    }
 
-        while let Some(ref_) = dirty_refs.pop() {
-            if self.subs.contains_key(&ref_) {
-                updated_subscribers.insert(ref_);
+    /// Abort the evaluation running on the current thread. Returns `false` if there is
+    /// none.
+    fn dep_tracing_fail(&self, error: Error) -> bool {
+        self.with_dep_tracing(|context| match context {
+            Some(context) => {
+                context.error.get_or_insert(error);
+                true
             }
+            None => false,
+        })
+    }
 
-            for (_, index_to, _) in deps.edges_directed(ref_, petgraph::Direction::Outgoing) {
-                dirty_refs.push(index_to);
+    /// Error aborting the evaluation running on the current thread.
+    fn dep_tracing_error(&self) -> Error {
+        self.with_dep_tracing(|context| match context.and_then(|context| context.error.clone()) {
+            Some(error) => error,
+            None => unreachable!(),
+        })
+    }
+
+    /// Function nodes being evaluated by the current task and the tasks that started it,
+    /// outermost first.
+    fn dep_tracing_nodes(&self) -> Vec<Index> {
+        self.with_dep_tracing(|context| match context {
+            Some(context) => {
+                let nodes = context.frames.iter().filter_map(|frame| frame.node);
+                context.callers.iter().copied().chain(nodes).collect()
             }
+            None => Vec::new(),
+        })
+    }
+
+    /// Labels of the functions being evaluated on the current thread, outermost first.
+    fn dep_tracing_call_chain(&self) -> Vec<String>
+    where
+        Sel: std::fmt::Debug,
+    {
+        let chain = self.dep_tracing_nodes();
+        let deps = self.deps.read().unwrap();
+        chain
+            .into_iter()
+            .filter_map(|r#ref| deps.node_weight(r#ref).map(node_label))
+            .collect()
+    }
+
+    /// If the node is already being evaluated by the current task or one of the tasks that
+    /// started it, return the chain of evaluations from it back to itself.
+    fn dep_tracing_find_cycle(&self, r#ref: Index) -> Option<Vec<Index>> {
+        let mut nodes = self.dep_tracing_nodes();
+        let start = nodes.iter().position(|&node| node == r#ref)?;
+        nodes.push(r#ref);
+        Some(nodes.split_off(start))
+    }
+
+    /// Add a dependency from the current node to the given node.
+    ///
+    /// When the dependency tracing stack is empty, there is no function being evaluated.
+    /// It means that the user is retrieving values from the database. There is no need to
+    /// track the dependencies in this case.
+    fn dep_tracing_add_dep(&self, dep: Index) {
+        self.with_dep_tracing(|context| {
+            if let Some(frame) = context.and_then(|context| context.frames.last_mut()) {
+                frame.deps.insert(dep);
+            }
+        });
+    }
+
+    pub fn graph(&self) -> &Arc<RwLock<DepGraph<Sel>>> {
+        &self.deps
+    }
+
+    /// Explain how the value of a node, e.g. `("pMax", Selector::Blok(b), t)`, was computed.
+    ///
+    /// Returns the tree of the function results and the source table values the node was
+    /// computed from, or `None` if the node was never evaluated.
+    pub fn explain(&self, node: &NodeT<Sel>) -> Option<Explanation<Sel>> {
+        let r#ref = *self.refs.read().unwrap().get(node)?;
+        let deps = self.deps.read().unwrap();
+        let value_cache = self.value_cache.read().unwrap();
+        Some(self.explain_ref(r#ref, &deps, &value_cache))
+    }
+
+    fn explain_ref(
+        &self,
+        r#ref: Index,
+        deps: &DepGraph<Sel>,
+        value_cache: &ValueCache<Sel>,
+    ) -> Explanation<Sel> {
+        let node @ (name, selector, t) = deps.node_weight(r#ref).unwrap();
+        let source = self.value_provider.quantity_source(name);
+        let is_function = source.is_none() && self.value_fns.read().unwrap().contains_key(node);
+        let value = if is_function {
+            value_cache.get(node).and_then(|result| result.as_ref().ok().cloned())
+        } else {
+            self.lookup_value(name, selector, t)
+        };
+        // Inputs in the order of their nodes, so that the explanation is stable.
+        let mut dependencies: Vec<Index> = deps.dependencies(r#ref).collect();
+        dependencies.sort_unstable();
+        let inputs = dependencies
+            .into_iter()
+            .map(|dep| self.explain_ref(dep, deps, value_cache))
+            .collect();
+
+        Explanation {
+            name,
+            selector: selector.clone(),
+            time: *t,
+            value,
+            source,
+            inputs,
+        }
+    }
+
+    /// Export the dependency graph, or a cone of it, in the Graphviz DOT format.
+    pub fn graph_to_dot(&self, scope: GraphScope<Sel>) -> String
+    where
+        Sel: std::fmt::Debug,
+    {
+        let nodes = self.graph_scope_nodes(scope);
+        self.deps.read().unwrap().to_dot(nodes.as_ref())
+    }
+
+    /// Export the dependency graph, or a cone of it, as a JSON node/edge list.
+    pub fn graph_to_json(&self, scope: GraphScope<Sel>) -> serde_json::Value
+    where
+        Sel: std::fmt::Debug,
+    {
+        let nodes = self.graph_scope_nodes(scope);
+        self.deps.read().unwrap().to_json(nodes.as_ref())
+    }
+
+    fn graph_scope_nodes(&self, scope: GraphScope<Sel>) -> Option<HashSet<Index>> {
+        let (node, direction) = match scope {
+            GraphScope::All => return None,
+            GraphScope::Upstream(node) => (node, petgraph::Direction::Incoming),
+            GraphScope::Downstream(node) => (node, petgraph::Direction::Outgoing),
+        };
+        let r#ref = match self.refs.read().unwrap().get(&node) {
+            Some(&r#ref) => r#ref,
+            None => return Some(HashSet::new()),
+        };
+        Some(self.deps.read().unwrap().cone(r#ref, direction))
+    }
+
+    /// Reclaim the graph slots freed by pruned nodes.
+    ///
+    /// Node indices change, so this must not be called while a function is being
+    /// evaluated.
+    pub fn compact_graph(&mut self) {
+        self.prune_graph();
+        let remap = self.deps.write().unwrap().compact();
+
+        for r#ref in self.refs.write().unwrap().values_mut() {
+            *r#ref = remap[r#ref];
+        }
+        for sub in self.subs.write().unwrap().values_mut() {
+            sub.refs = sub.refs.iter().filter_map(|r| remap.get(r).copied()).collect();
+        }
+    }
+
+    /// Subscribe to the value computed by `value_fn`, e.g. `|db| pMax(db, b, t)`.
+    ///
+    /// The closure is kept together with its captured arguments and re-evaluated by
+    /// [`Db::sync_changes`] whenever any of the values it reads changes. The subscription
+    /// lasts until the returned handle is dropped.
+    pub fn subscribe<V: ValueType>(
+        &mut self,
+        value_fn: impl Fn(&Self) -> V + Send + Sync + 'static,
+    ) -> Subscription
+    where
+        Sel: 'static,
+        T: 'static,
+        VP: 'static,
+    {
+        let call = self.dep_tracing_function_call(None, false);
+        let value = value_fn(self).into_value();
+        let refs = self.dep_tracing_function_return(call).unwrap_or_else(|error| {
+            panic!("{}", error.expect("subscription is the outermost evaluation"))
+        });
+
+        let id = self.next_subscription_id;
+        self.next_subscription_id += 1;
+        self.subs.write().unwrap().insert(
+            id,
+            Subscriber {
+                value_fn: Arc::new(move |db| value_fn(db).into_value()),
+                refs,
+                value,
+            },
+        );
+
+        // Pruning is deferred to the next `&mut self` call, because other threads may be
+        // evaluating functions (and holding node indices) when the handle is dropped.
+        let subs = Arc::downgrade(&self.subs);
+        let needs_pruning = Arc::downgrade(&self.needs_pruning);
+        let released_refs = Arc::downgrade(&self.released_refs);
+        let unsubscribe = move |id| {
+            let (Some(subs), Some(needs_pruning), Some(released_refs)) =
+                (subs.upgrade(), needs_pruning.upgrade(), released_refs.upgrade())
+            else {
+                // The database is already gone.
+                return;
+            };
+            if let Some(sub) = subs.write().unwrap().remove(&id) {
+                released_refs.lock().unwrap().extend(sub.refs);
+            }
+            needs_pruning.store(true, Ordering::Release);
+        };
+
+        Subscription {
+            id,
+            unsubscribe: Some(Box::new(unsubscribe)),
+        }
+    }
+
+    pub fn unsubscribe(&mut self, subscription: Subscription) {
+        std::mem::drop(subscription);
+        self.prune_graph();
+    }
+
+    /// Remove the graph nodes that no subscription or cached value depends on, if any
+    /// subscription was dropped since the last pruning.
+    ///
+    /// The cached results that only the dropped subscriptions depended on are evicted
+    /// first, as they would keep their nodes otherwise.
+    fn prune_graph(&mut self) {
+        if !self.needs_pruning.swap(false, Ordering::Acquire) {
+            return;
+        }
+        let released_refs = std::mem::take(&mut *self.released_refs.lock().unwrap());
+        {
+            let mut refs = self.refs.write().unwrap();
+            let mut deps = self.deps.write().unwrap();
+            let mut value_cache = self.value_cache.write().unwrap();
+            let mut value_fns = self.value_fns.write().unwrap();
+            let subs = self.subs.read().unwrap();
+            evict_released(&deps, &mut value_cache, &subs, released_refs);
+            prune_graph(&mut refs, &mut deps, &value_cache, &mut value_fns, &subs);
+        }
+        let refs = self.refs.read().unwrap();
+        self.cutoff_baselines.retain(|key, _| refs.contains_key(key));
+    }
+
+    /// Re-evaluate the subscriptions that read any of the `dirty_refs`.
+    ///
+    /// Subscriptions are evaluated in the topological order of the nodes they read, so
+    /// that shared intermediate results are computed (and cached) before their dependents.
+    pub fn recompute_subscriptions(
+        &mut self,
+        dirty_refs: &HashSet<Index>,
+    ) -> Vec<(SubscriptionId, Value, Value)> {
+        let position: HashMap<Index, usize> = self
+            .topological_order(dirty_refs)
+            .into_iter()
+            .enumerate()
+            .map(|(i, r#ref)| (r#ref, i))
+            .collect();
+
+        let mut dirty_subs: Vec<(usize, SubscriptionId)> = self
+            .subs
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(&id, sub)| {
+                let last = sub.refs.iter().filter_map(|r| position.get(r)).max()?;
+                Some((*last, id))
+            })
+            .collect();
+        dirty_subs.sort_unstable();
+
+        let mut updates = Vec::with_capacity(dirty_subs.len());
+        for (_, id) in dirty_subs {
+            let value_fn = match self.subs.read().unwrap().get(&id) {
+                Some(sub) => sub.value_fn.clone(),
+                // Unsubscribed by one of the previously evaluated subscriptions.
+                None => continue,
+            };
+            let call = self.dep_tracing_function_call(None, false);
+            let new_value = value_fn(self);
+            let refs = self.dep_tracing_function_return(call).unwrap_or_else(|error| {
+                panic!("{}", error.expect("subscription is the outermost evaluation"))
+            });
+
+            let mut subs = self.subs.write().unwrap();
+            let Some(sub) = subs.get_mut(&id) else {
+                continue;
+            };
+            sub.refs = refs;
+            // The reported value is kept, so that small changes do not add up unreported.
+            if self
+                .early_cutoff
+                .is_some_and(|epsilon| sub.value.approx_eq(&new_value, epsilon))
+            {
+                continue;
+            }
+            let old_value = std::mem::replace(&mut sub.value, new_value.clone());
+            updates.push((id, old_value, new_value));
+        }
+        updates
+    }
+
+    /// Order the given nodes so that every node comes after the nodes it depends on.
+    fn topological_order(&self, refs: &HashSet<Index>) -> Vec<Index> {
+        let deps = self.deps.read().unwrap();
+
+        let mut in_degree: HashMap<Index, usize> = refs.iter().map(|&r| (r, 0)).collect();
+        for &r#ref in refs.iter() {
+            for index_to in deps.dependents(r#ref) {
+                if let Some(degree) = in_degree.get_mut(&index_to) {
+                    *degree += 1;
+                }
+            }
+        }
+
+        let mut ready: Vec<Index> = in_degree
+            .iter()
+            .filter(|(_, &degree)| degree == 0)
+            .map(|(&r#ref, _)| r#ref)
+            .collect();
+        let mut order = Vec::with_capacity(refs.len());
+        while let Some(r#ref) = ready.pop() {
+            order.push(r#ref);
+            for index_to in deps.dependents(r#ref) {
+                if let Some(degree) = in_degree.get_mut(&index_to) {
+                    *degree -= 1;
+                    if *degree == 0 {
+                        ready.push(index_to);
+                    }
+                }
+            }
+        }
+        order
+    }
+
+    /// Set a new value and invalidate everything that depends on it.
+    ///
+    /// Returns the invalidated nodes; pass them to [`Db::recompute_subscriptions`] to
+    /// refresh the affected subscriptions. A value of a different type than the quantity
+    /// is not set and [`Error::UnexpectedValueType`] is returned.
+    ///
+    /// Use [`Db::update_many`] for bulk updates.
+    pub fn update(
+        &mut self,
+        name: &'static str,
+        selector: &Sel,
+        t: &Time,
+        new_value: impl Into<Value>,
+    ) -> Result<HashSet<Index>, Error> {
+        self.update_many([(name, selector.clone(), *t, new_value)])
+    }
+
+    /// Set all the given values first and then invalidate their dependents in a single
+    /// traversal of the dependency graph.
+    ///
+    /// Returns the invalidated nodes, like [`Db::update`]. With early cutoff (see
+    /// [`Db::set_early_cutoff`]), returns only the nodes whose value changed.
+    ///
+    /// If a value has the wrong type, the other values are still set and the error is
+    /// returned. The subscriptions reading them are recomputed and their updates are
+    /// returned by the next [`Db::apply_changes`].
+    pub fn update_many<V: Into<Value>>(
+        &mut self,
+        values: impl IntoIterator<Item = (&'static str, Sel, Time, V)>,
+    ) -> Result<HashSet<Index>, Error> {
+        self.update_many_known_at(values, Time::now())
+    }
+
+    /// Same as [`Db::update_many`], recording the changes in the history (see
+    /// [`Db::enable_history`]) as known since `knowledge_time`.
+    pub fn update_many_known_at<V: Into<Value>>(
+        &mut self,
+        values: impl IntoIterator<Item = (&'static str, Sel, Time, V)>,
+        knowledge_time: Time,
+    ) -> Result<HashSet<Index>, Error> {
+        self.prune_graph();
+
+        let mut changed_refs = Vec::new();
+        let mut error = None;
+        for (name, selector, t, new_value) in values {
+            let change = ValueChange::Set(new_value.into());
+            match self.set_value_known_at(name, selector, t, change, knowledge_time) {
+                Ok(changed_ref) => changed_refs.extend(changed_ref),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        let dirty_refs = self.invalidate_changed(changed_refs);
+        self.dirty_refs_or_error(dirty_refs, error)
+    }
+
+    /// Return the invalidated nodes, or the error once the subscriptions reading them
+    /// are recomputed. Their updates are returned by the next [`Db::apply_changes`].
+    fn dirty_refs_or_error(
+        &mut self,
+        dirty_refs: HashSet<Index>,
+        error: Option<Error>,
+    ) -> Result<HashSet<Index>, Error> {
+        match error {
+            Some(error) => {
+                let updates = self.recompute_subscriptions(&dirty_refs);
+                self.unreported_updates.extend(updates);
+                Err(error)
+            }
+            None => Ok(dirty_refs),
+        }
+    }
+
+    /// Change the value and return its node if its dependents need to be invalidated.
+    fn set_value_known_at(
+        &mut self,
+        name: &'static str,
+        selector: Sel,
+        t: Time,
+        change: ValueChange,
+        knowledge_time: Time,
+    ) -> Result<Option<Index>, Error> {
+        let new_value = match &change {
+            ValueChange::Set(value) => Some(value.clone()),
+            ValueChange::Clear | ValueChange::Remove => None,
+        };
+        let key = (name, selector, t);
+        let mut changed_ref = self.refs.read().unwrap().get(&key).copied();
+        if let (Some(epsilon), Some(_)) = (self.early_cutoff, changed_ref) {
+            let baseline = match self.cutoff_baselines.get(&key) {
+                Some(baseline) => baseline.clone(),
+                None => self.lookup_value(name, &key.1, &t),
+            };
+            let unchanged = match (&baseline, &new_value) {
+                (Some(old_value), Some(new_value)) => old_value.approx_eq(new_value, epsilon),
+                (old_value, new_value) => old_value.is_none() && new_value.is_none(),
+            };
+            if unchanged {
+                self.cutoff_baselines.entry(key.clone()).or_insert(baseline);
+                changed_ref = None;
+            }
+        }
+        match self.overlay.as_mut() {
+            Some(overlay) => {
+                let new_value = new_value.expect("values are not cleared in a scenario");
+                // The live value provider is not changed, so the type is checked against
+                // the value being overridden.
+                let live_value = self.value_provider.get_value_opt(name, &key.1, &t);
+                if let Some(live_value) = live_value {
+                    if live_value.type_name() != new_value.type_name() {
+                        return Err(Error::UnexpectedValueType {
+                            name,
+                            expected: live_value.type_name(),
+                            actual: new_value.type_name(),
+                        });
+                    }
+                }
+                overlay.values.insert(key, new_value);
+            }
+            None => {
+                let old_value = self.value_provider.get_value_opt(name, &key.1, &t);
+                let (name, selector, t) = key.clone();
+                let value_provider = Arc::get_mut(&mut self.value_provider)
+                    .expect("value provider is not shared with a scenario");
+                match change {
+                    ValueChange::Set(value) => value_provider.set_value(name, selector, t, value)?,
+                    ValueChange::Clear => value_provider.clear_value(name, selector, t),
+                    ValueChange::Remove => {
+                        value_provider.remove_value(name, &selector, &t);
+                    }
+                }
+                self.record_history(&key, old_value, new_value, knowledge_time);
+            }
+        }
+        Ok(changed_ref)
+    }
+
+    /// Remove all the values of a truncated table and return the nodes whose dependents
+    /// need to be invalidated.
+    fn remove_table_known_at(&mut self, table_name: &str, knowledge_time: Time) -> Vec<Index> {
+        let removed = Arc::get_mut(&mut self.value_provider)
+            .expect("value provider is not shared with a scenario")
+            .remove_table(table_name);
+        let mut changed_refs = Vec::new();
+        for (name, selector, t, old_value) in removed {
+            let key = (name, selector, t);
+            changed_refs.extend(self.refs.read().unwrap().get(&key).copied());
+            self.record_history(&key, Some(old_value), None, knowledge_time);
+        }
+        changed_refs
+    }
+
+    fn record_history(
+        &mut self,
+        key: &NodeT<Sel>,
+        old_value: Option<Value>,
+        new_value: Option<Value>,
+        knowledge_time: Time,
+    ) {
+        let Some(history) = self.history.as_mut() else {
+            return;
+        };
+        Arc::get_mut(history)
+            .expect("history is not shared with a scenario")
+            .entry(key.clone())
+            .or_insert_with(|| {
+                let mut changes = TimeSeriesChanges::default();
+                changes.push(Time(i64::MIN), old_value);
+                changes
+            })
+            .set(&knowledge_time, new_value);
+    }
+
+    /// Invalidate the dependents of the changed nodes, see [`Db::update_many`].
+    fn invalidate_changed(&mut self, changed_refs: Vec<Index>) -> HashSet<Index> {
+        let dirty_refs: HashSet<Index> = match self.early_cutoff {
+            None => self.invalidate(changed_refs).into_keys().collect(),
+            Some(epsilon) => self.reevaluate(changed_refs, epsilon),
+        };
+        if !self.cutoff_baselines.is_empty() {
+            let deps = self.deps.read().unwrap();
+            for r#ref in dirty_refs.iter() {
+                if let Some(key) = deps.node_weight(*r#ref) {
+                    self.cutoff_baselines.remove(key);
+                }
+            }
+        }
+        if let Some(overlay) = self.overlay.as_mut() {
+            overlay.affected.extend(dirty_refs.iter().copied());
+        }
+        dirty_refs
+    }
+
+    /// Re-evaluate the cached transitive dependents of the given nodes bottom-up, stopping
+    /// at the nodes whose value changed by at most `epsilon`.
+    ///
+    /// Returns the given nodes and the function nodes whose value changed.
+    fn reevaluate(&mut self, changed_refs: Vec<Index>, epsilon: f64) -> HashSet<Index> {
+        // Drop the cached values first, so that a function reading a dependent that is
+        // not re-evaluated yet does not get its stale value.
+        let mut old_values = self.invalidate(changed_refs.clone());
+        let affected: HashSet<Index> = old_values.keys().copied().collect();
+
+        let mut changed: HashSet<Index> = changed_refs.into_iter().collect();
+        for r#ref in self.topological_order(&affected) {
+            if changed.contains(&r#ref) {
+                continue;
+            }
+            let Some((key, old_result)) = old_values.remove(&r#ref).flatten() else {
+                // Not cached, so there is nothing to compare with.
+                changed.insert(r#ref);
+                continue;
+            };
+
+            let deps_changed = self
+                .deps
+                .read()
+                .unwrap()
+                .dependencies(r#ref)
+                .any(|dep| changed.contains(&dep));
+            if !deps_changed {
+                self.value_cache.write().unwrap().entry(key).or_insert(old_result);
+                continue;
+            }
+
+            // The node may have been re-evaluated already by a function reading it.
+            let cached_result = self.value_cache.read().unwrap().get(&key).cloned();
+            let new_result = match cached_result {
+                Some(result) => Some(result),
+                None => {
+                    let value_fn = self.value_fns.read().unwrap().get(&key).cloned();
+                    value_fn.and_then(|value_fn| {
+                        self.evaluate_fn(r#ref, key.clone(), &*value_fn, true)
+                    })
+                }
+            };
+            // Errors are always propagated.
+            let unchanged = match (old_result, new_result) {
+                (Ok(old_value), Some(Ok(new_value))) => {
+                    let baseline = self.cutoff_baselines.entry(key).or_insert(Some(old_value));
+                    baseline.as_ref().is_some_and(|value| value.approx_eq(&new_value, epsilon))
+                }
+                _ => false,
+            };
+            if !unchanged {
+                changed.insert(r#ref);
+            }
+        }
+        changed
+    }
+
+    /// Drop the cached values of the given nodes and all their transitive dependents.
+    ///
+    /// Returns the invalidated nodes together with their dropped cache entries.
+    fn invalidate(
+        &self,
+        changed_refs: Vec<Index>,
+    ) -> Invalidated<Sel> {
+        let deps = self.deps.read().unwrap();
+        let mut value_cache = self.value_cache.write().unwrap();
+
+        let mut visited = HashMap::new();
+        let mut dirty_refs = changed_refs;
+
+        while let Some(ref_) = dirty_refs.pop() {
+            if visited.contains_key(&ref_) {
+                continue;
+            }
+
+            let evicted = deps
+                .node_weight(ref_)
+                .and_then(|node| value_cache.remove_entry(node));
+            visited.insert(ref_, evicted);
+
+            for index_to in deps.dependents(ref_) {
+                dirty_refs.push(index_to);
+            }
+        }
+
+        visited
+    }
+}
+
+/// Drop the cached values of the nodes that only the released nodes (read by dropped
+/// subscriptions) depend on.
+fn evict_released<Sel, T, VP>(
+    deps: &DepGraph<Sel>,
+    value_cache: &mut ValueCache<Sel>,
+    subs: &Subscribers<Sel, T, VP>,
+    released_refs: Vec<Index>,
+) where
+    Sel: Clone + Eq + Hash,
+    T: TableFromTupleData + TableValues<Sel>,
+    VP: ValueProvider<Sel>,
+{
+    if released_refs.is_empty() {
+        return;
+    }
+    let subscribed_refs = subs.values().flat_map(|sub| sub.refs.iter().copied());
+    let still_needed = upstream_cone(deps, subscribed_refs);
+    for r#ref in upstream_cone(deps, released_refs) {
+        if still_needed.contains(&r#ref) {
+            continue;
+        }
+        if let Some(key) = deps.node_weight(r#ref) {
+            value_cache.remove(key);
+        }
+    }
+}
+
+/// The given nodes and all the nodes they transitively depend on.
+fn upstream_cone<Sel>(
+    deps: &DepGraph<Sel>,
+    refs: impl IntoIterator<Item = Index>,
+) -> HashSet<Index> {
+    let mut refs: Vec<Index> = refs.into_iter().collect();
+    let mut cone = HashSet::new();
+    while let Some(r#ref) = refs.pop() {
+        if !cone.insert(r#ref) {
+            continue;
+        }
+        refs.extend(deps.dependencies(r#ref));
+    }
+    cone
+}
+
+/// Remove the nodes that no subscription or cached value depends on.
+fn prune_graph<Sel, T, VP>(
+    refs: &mut Refs<Sel>,
+    deps: &mut DepGraph<Sel>,
+    value_cache: &ValueCache<Sel>,
+    value_fns: &mut ValueFns<Sel, T, VP>,
+    subs: &Subscribers<Sel, T, VP>,
+) where
+    Sel: Clone + Eq + Hash,
+    T: TableFromTupleData + TableValues<Sel>,
+    VP: ValueProvider<Sel>,
+{
+    let needed_refs = subs
+        .values()
+        .flat_map(|sub| sub.refs.iter().copied())
+        .chain(value_cache.keys().filter_map(|key| refs.get(key).copied()));
+    let needed = upstream_cone(deps, needed_refs);
+
+    refs.retain(|key, r#ref| {
+        if needed.contains(r#ref) {
+            return true;
+        }
+        deps.remove_node(*r#ref);
+        value_fns.remove(key);
+        false
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::replication::pgoutput;
+
+    #[derive(Default)]
+    struct TestValueProvider {
+        values: HashMap<(&'static str, i64, Time), Value>,
+    }
+
+    impl ValueProvider<i64> for TestValueProvider {
+        async fn from_pool(_pool: &PgPool) -> Self {
+            Self::default()
+        }
+
+        fn set_value(
+            &mut self,
+            name: &'static str,
+            selector: i64,
+            t: Time,
+            value: Value,
+        ) -> Result<(), Error> {
+            // A quantity keeps the type of its first value.
+            let expected = self.values.iter().find(|((n, _, _), _)| *n == name);
+            if let Some((_, expected)) = expected {
+                if expected.type_name() != value.type_name() {
+                    return Err(Error::UnexpectedValueType {
+                        name,
+                        expected: expected.type_name(),
+                        actual: value.type_name(),
+                    });
+                }
+            }
+            self.values.insert((name, selector, t), value);
+            Ok(())
+        }
+
+        fn get_value(&self, name: &'static str, selector: &i64, t: &Time) -> Value {
+            self.get_value_opt(name, selector, t).unwrap()
+        }
+
+        fn get_value_opt(&self, name: &'static str, selector: &i64, t: &Time) -> Option<Value> {
+            self.values.get(&(name, *selector, *t)).cloned()
+        }
+
+        fn clear_value(&mut self, name: &'static str, selector: i64, t: Time) {
+            self.values.remove(&(name, selector, t));
+        }
+
+        fn remove_value(&mut self, name: &'static str, selector: &i64, t: &Time) -> Option<Value> {
+            self.values.remove(&(name, *selector, *t))
+        }
+
+        fn remove_table(&mut self, table_name: &str) -> Vec<(&'static str, i64, Time, Value)> {
+            let keys: Vec<_> = self
+                .values
+                .keys()
+                .filter(|(name, _, _)| {
+                    self.quantity_source(name).is_some_and(|(table, _)| table == table_name)
+                })
+                .copied()
+                .collect();
+            keys.into_iter()
+                .map(|key| {
+                    let value = self.values.remove(&key).unwrap();
+                    (key.0, key.1, key.2, value)
+                })
+                .collect()
+        }
+
+        fn quantity_source(&self, name: &'static str) -> Option<(&'static str, &'static str)> {
+            match name {
+                "a" => Some(("Table", "a")),
+                "b" => Some(("Table", "b")),
+                _ => None,
+            }
+        }
+    }
+
+    /// Row of `Table` with the selector and the value of `a` at `Time(0)`.
+    struct TestTable {
+        selector: i64,
+        a: Option<f64>,
+    }
+
+    impl TableFromTupleData for TestTable {
+        fn from_tuple_data(row: &TupleRow) -> Result<Self, Error> {
+            if row.relation_name() != "Table" {
+                return Err(Error::UnknownTable {
+                    table_name: row.relation_name().to_string(),
+                });
+            }
+            Ok(TestTable {
+                selector: row.decode("selector")?,
+                a: row.decode("a")?,
+            })
+        }
+
+        fn table_names() -> Vec<&'static str> {
+            vec!["Table"]
+        }
+    }
+
+    impl TableValues<i64> for TestTable {
+        fn time(&self) -> Time {
+            Time(0)
+        }
+
+        fn selector(&self) -> i64 {
+            self.selector
+        }
+
+        fn values(&self) -> Vec<(&'static str, Option<Value>)> {
+            vec![("a", self.a.map(Value::from))]
+        }
+    }
+
+    type TestDb = Db<i64, TestTable, TestValueProvider>;
+
+    fn test_db() -> TestDb {
+        let mut vp = TestValueProvider::default();
+        vp.set_value("a", 1, Time(0), 1.0.into()).unwrap();
+        vp.set_value("b", 1, Time(0), 2.0.into()).unwrap();
+        TestDb::from_value_provider(vp)
+    }
+
+    /// Counter of function evaluations.
+    #[derive(Clone, Default)]
+    struct Calls(Arc<AtomicUsize>);
+
+    impl Calls {
+        fn get(&self) -> usize {
+            self.0.load(Ordering::Relaxed)
+        }
+
+        fn increment(&self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn sum(db: &TestDb, calls: &Calls) -> f64 {
+        let calls = calls.clone();
+        db.register_fn("sum", 1, Time(0), move |db| {
+            calls.increment();
+            db.get_value("a", 1, Time(0)) + db.get_value("b", 1, Time(0))
+        })
+    }
+
+    fn double_sum(db: &TestDb, calls: &Calls) -> f64 {
+        let calls = calls.clone();
+        db.register_fn("double_sum", 1, Time(0), move |db| 2.0 * sum(db, &calls))
+    }
+
+    #[test]
+    fn cached_value_is_not_recomputed() {
+        let db = test_db();
+        let calls = Calls::default();
+
+        assert_eq!(sum(&db, &calls), 3.0);
+        assert_eq!(sum(&db, &calls), 3.0);
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn update_invalidates_transitive_dependents() {
+        let mut db = test_db();
+        let calls = Calls::default();
+
+        assert_eq!(double_sum(&db, &calls), 6.0);
+        db.update("a", &1, &Time(0), 5.0).unwrap();
+        assert_eq!(double_sum(&db, &calls), 14.0);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn explain_lists_function_results_and_source_values() {
+        let db = test_db();
+        let calls = Calls::default();
+        double_sum(&db, &calls);
+
+        let explanation = db.explain(&("double_sum", 1, Time(0))).unwrap();
+        assert_eq!(explanation.value, Some(6.0.into()));
+        assert_eq!(explanation.inputs.len(), 1);
+        assert_eq!(explanation.inputs[0].name, "sum");
+        assert_eq!(explanation.inputs[0].value, Some(3.0.into()));
+
+        let leaves: Vec<_> = explanation
+            .leaves()
+            .into_iter()
+            .map(|leaf| (leaf.source, leaf.value.clone()))
+            .collect();
+        assert_eq!(
+            leaves,
+            vec![
+                (Some(("Table", "a")), Some(1.0.into())),
+                (Some(("Table", "b")), Some(2.0.into()))
+            ]
+        );
+        assert!(db.explain(&("unknown", 1, Time(0))).is_none());
+    }
+
+    #[test]
+    fn explain_drops_inputs_of_a_previous_branch() {
+        let mut db = test_db();
+        fn pick(db: &TestDb) -> f64 {
+            db.register_fn("pick", 1, Time(0), |db| {
+                match db.get_value("b", 1, Time(0)) > 1.5 {
+                    true => db.get_value("a", 1, Time(0)),
+                    false => db.get_value("c", 1, Time(0)),
+                }
+            })
+        }
+        assert_eq!(pick(&db), 1.0);
+
+        db.update("c", &1, &Time(0), 7.0).unwrap();
+        db.update("b", &1, &Time(0), 1.0).unwrap();
+        assert_eq!(pick(&db), 7.0);
+
+        let explanation = db.explain(&("pick", 1, Time(0))).unwrap();
+        let inputs: Vec<_> = explanation
+            .inputs
+            .iter()
+            .map(|input| (input.name, input.source, input.value.clone()))
+            .collect();
+        // `c` has no known source, its value is still shown.
+        assert_eq!(
+            inputs,
+            vec![
+                ("b", Some(("Table", "b")), Some(1.0.into())),
+                ("c", None, Some(7.0.into()))
+            ]
+        );
+        assert_eq!(db.update("a", &1, &Time(0), 3.0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn update_many_invalidates_once() {
+        let mut db = test_db();
+        let calls = Calls::default();
+
+        assert_eq!(double_sum(&db, &calls), 6.0);
+        let dirty_refs = db.update_many([("a", 1, Time(0), 5.0), ("b", 1, Time(0), 3.0)]).unwrap();
+        assert_eq!(dirty_refs.len(), 4);
+        assert_eq!(double_sum(&db, &calls), 16.0);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn update_of_wrong_type_returns_error() {
+        let mut db = test_db();
+        let calls = Calls::default();
+        let sub = db.subscribe(|db| db.get_value("b", 1, Time(0)));
+
+        assert_eq!(double_sum(&db, &calls), 6.0);
+        let error = db
+            .update_many([("a", 1, Time(0), Value::from(true)), ("b", 1, Time(0), 3.0.into())])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unexpected type of a: actual: bool, expected: f64"
+        );
+        assert_eq!(double_sum(&db, &calls), 8.0);
+        let updates = db.apply_changes(Vec::new()).unwrap();
+        assert_eq!(updates, vec![(sub.id(), 2.0.into(), 3.0.into())]);
+
+        let mut scenario = db.scenario();
+        assert!(scenario.update("a", &1, &Time(0), "coal").is_err());
+        assert_eq!(scenario.get_value("a", 1, Time(0)), 1.0);
+    }
+
+    #[test]
+    fn subscriptions_are_recomputed_after_update() {
+        let mut db = test_db();
+        let sub = db.subscribe(|db| {
+            db.register_fn("sum", 1, Time(0), |db| {
+                db.get_value("a", 1, Time(0)) + db.get_value("b", 1, Time(0))
+            })
+        });
+        let other = db.subscribe(|db| db.get_value("b", 1, Time(0)));
+
+        let dirty_refs = db.update("a", &1, &Time(0), 5.0).unwrap();
+        let updates = db.recompute_subscriptions(&dirty_refs);
+
+        assert_eq!(updates, vec![(sub.id(), 3.0.into(), 7.0.into())]);
+        assert_ne!(sub.id(), other.id());
+    }
+
+    #[test]
+    fn dropping_subscription_prunes_graph() {
+        let mut db = test_db();
+        let a = db.subscribe(|db| db.get_value("a", 1, Time(0)));
+        let b = db.subscribe(|db| db.get_value("b", 1, Time(0)));
+        assert_eq!(db.graph().read().unwrap().node_count(), 2);
+
+        db.unsubscribe(a);
+        assert_eq!(db.graph().read().unwrap().node_count(), 1);
+
+        let dirty_refs = db.update("b", &1, &Time(0), 5.0).unwrap();
+        let updates = db.recompute_subscriptions(&dirty_refs);
+        assert_eq!(updates, vec![(b.id(), 2.0.into(), 5.0.into())]);
+
+        db.compact_graph();
+        let dirty_refs = db.update("b", &1, &Time(0), 7.0).unwrap();
+        let updates = db.recompute_subscriptions(&dirty_refs);
+        assert_eq!(updates, vec![(b.id(), 5.0.into(), 7.0.into())]);
+
+        std::mem::drop(b);
+        assert!(db.update("b", &1, &Time(0), 6.0).unwrap().is_empty());
+        assert_eq!(db.graph().read().unwrap().node_count(), 0);
+    }
+
+    #[test]
+    fn unsubscribing_evicts_results_computed_only_for_the_subscription() {
+        let mut db = test_db();
+        let calls = Calls::default();
+        let sub = {
+            let calls = calls.clone();
+            db.subscribe(move |db| double_sum(db, &calls))
+        };
+        let other = db.subscribe(|db| {
+            db.register_fn("doubled_b", 1, Time(0), |db| 2.0 * db.get_value("b", 1, Time(0)))
+        });
+        assert_eq!(db.graph().read().unwrap().node_count(), 5);
+
+        db.unsubscribe(sub);
+        assert_eq!(db.graph().read().unwrap().node_count(), 2);
+        assert_eq!(db.value_cache.read().unwrap().len(), 1);
+
+        db.unsubscribe(other);
+        assert_eq!(db.graph().read().unwrap().node_count(), 0);
+        assert!(db.value_cache.read().unwrap().is_empty());
+        assert!(db.value_fns.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn concurrent_evaluations_track_their_own_dependencies() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<TestDb>();
+
+        let mut vp = TestValueProvider::default();
+        for i in 0..8 {
+            vp.set_value("a", i, Time(0), (i as f64).into()).unwrap();
+        }
+        let mut db = TestDb::from_value_provider(vp);
+
+        let square = |db: &TestDb, i: i64| {
+            db.register_fn("square", i, Time(0), move |db| {
+                let a = db.get_value("a", i, Time(0));
+                a * a
+            })
+        };
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let db = &db;
+                scope.spawn(move || assert_eq!(square(db, i), (i * i) as f64));
+            }
+        });
+
+        let dirty_refs = db.update("a", &3, &Time(0), 10.0).unwrap();
+        assert_eq!(dirty_refs.len(), 2);
+        assert_eq!(square(&db, 3), 100.0);
+    }
+
+    #[test]
+    fn eval_many_records_dependencies() {
+        let mut vp = TestValueProvider::default();
+        for t in 0..96 {
+            vp.set_value("a", 1, Time(t), (t as f64).into()).unwrap();
+            vp.set_value("a", 2, Time(t), (2.0 * t as f64).into()).unwrap();
+        }
+        let mut db = TestDb::from_value_provider(vp);
+
+        fn scaled(db: &TestDb, (i, k): (i64, f64), t: Time) -> f64 {
+            db.register_fn("scaled", i, t, move |db| k * db.get_value("a", i, t))
+        }
+        let requests: Vec<_> = (0..96)
+            .flat_map(|t| [(scaled, (1, 2.0), Time(t)), (scaled, (2, 1.0), Time(t))])
+            .collect();
+
+        let values = db.eval_many(&requests);
+        assert_eq!(values.len(), 192);
+        assert!(values.chunks(2).all(|v| v[0] == v[1]));
+
+        let dirty_refs = db.update("a", &2, &Time(5), 0.0).unwrap();
+        assert_eq!(dirty_refs.len(), 2);
+        assert_eq!(scaled(&db, (2, 1.0), Time(5)), 0.0);
+    }
+
+    #[test]
+    fn eval_many_runs_calls_in_parallel() {
+        let db = test_db();
+        static THREADS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+        fn slow(db: &TestDb, i: i64, t: Time) -> f64 {
+            db.register_fn("slow", i, t, move |_| {
+                THREADS.lock().unwrap().extend(rayon::current_thread_index());
+                // Long enough for the idle threads to steal the other calls.
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                i as f64
+            })
+        }
+        let requests: Vec<_> = (0..8).map(|i| (slow, i, Time(0))).collect();
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let values = pool.install(|| db.eval_many(&requests));
+
+        assert_eq!(values, (0..8).map(|i| i as f64).collect::<Vec<_>>());
+        let threads: HashSet<usize> = THREADS.lock().unwrap().iter().copied().collect();
+        assert!(threads.len() > 1, "evaluated on threads {:?}", threads);
+    }
+
+    #[test]
+    fn nested_eval_many_keeps_dependencies_apart() {
+        let mut vp = TestValueProvider::default();
+        for i in 0..8 {
+            for t in 0..8 {
+                vp.set_value("a", i, Time(t), 1.0.into()).unwrap();
+            }
+        }
+        let mut db = TestDb::from_value_provider(vp);
+
+        fn cell(db: &TestDb, i: i64, t: Time) -> f64 {
+            db.register_fn("cell", i, t, move |db| db.get_value("a", i, t))
+        }
+        fn row(db: &TestDb, i: i64, _: Time) -> f64 {
+            db.register_fn("row", i, Time(0), move |db| {
+                let requests: Vec<_> = (0..8).map(|t| (cell, i, Time(t))).collect();
+                db.eval_many(&requests).into_iter().sum()
+            })
+        }
+        let requests: Vec<_> = (0..8).map(|i| (row, i, Time(0))).collect();
+
+        // Threads waiting for the cells of one row steal the other rows.
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let values = pool.install(|| db.eval_many(&requests));
+        assert_eq!(values, vec![8.0; 8]);
+
+        for i in 0..8 {
+            let explanation = db.explain(&("row", i, Time(0))).unwrap();
+            assert_eq!(explanation.inputs.len(), 8);
+            assert!(explanation.inputs.iter().all(|input| input.selector == i));
+        }
+        let dirty_refs = db.update("a", &3, &Time(5), 2.0).unwrap();
+        assert_eq!(dirty_refs.len(), 3);
+        assert_eq!(row(&db, 3, Time(0)), 9.0);
+    }
+
+    #[test]
+    fn panicking_function_leaves_no_frames_behind() {
+        let mut db = test_db();
+        fn inner(db: &TestDb) -> f64 {
+            db.register_fn("inner", 1, Time(0), |db| db.get_value("c", 1, Time(0)))
+        }
+        fn outer(db: &TestDb) -> f64 {
+            db.register_fn("outer", 1, Time(0), |db| inner(db) + 1.0)
+        }
+
+        // `c` is missing, so reading it panics.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| outer(&db)));
+        assert!(result.is_err());
+        assert!(DEP_TRACING.with_borrow(|contexts| contexts.is_empty()));
+
+        db.update("c", &1, &Time(0), 2.0).unwrap();
+        assert_eq!(db.try_eval(outer).unwrap(), 3.0);
+    }
+
+    fn ping(db: &TestDb, t: Time) -> f64 {
+        db.register_fn("ping", 1, t, move |db| pong(db, t))
+    }
+
+    fn pong(db: &TestDb, t: Time) -> f64 {
+        db.register_fn("pong", 1, t, move |db| ping(db, t) + 1.0)
+    }
+
+    #[test]
+    fn cyclic_dependency_is_reported() {
+        let db = test_db();
+
+        let error = db.try_eval(|db| ping(db, Time(0))).unwrap_err();
+        match &error {
+            Error::CyclicDependency { path } => assert_eq!(path.len(), 3),
+            _ => panic!("unexpected error: {}", error),
+        }
+        assert_eq!(
+            error.to_string(),
+            "Cyclic dependency: ping(1, 1970-01-01 00:00:00) -> \
+             pong(1, 1970-01-01 00:00:00) -> ping(1, 1970-01-01 00:00:00)"
+        );
+        assert!(db.value_cache.read().unwrap().is_empty());
+    }
+
+    fn try_ping(db: &TestDb, t: Time) -> Result<f64, Error> {
+        db.try_register_fn("ping", 1, t, move |db| try_pong(db, t))
+    }
+
+    fn try_pong(db: &TestDb, t: Time) -> Result<f64, Error> {
+        db.try_register_fn("pong", 1, t, move |db| {
+            Ok(db.get_value("a", 1, t) + try_ping(db, t)?)
+        })
+    }
+
+    #[test]
+    fn cyclic_dependency_of_fallible_functions_is_not_cached() {
+        let db = test_db();
+        let outer = |db: &TestDb| {
+            db.try_register_fn("outer", 1, Time(0), |db| try_ping(db, Time(0)))
+        };
+
+        for _ in 0..2 {
+            match outer(&db) {
+                Err(Error::CyclicDependency { path }) => assert_eq!(path.len(), 3),
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+        assert!(db.value_cache.read().unwrap().is_empty());
+        assert_eq!(db.deps.read().unwrap().edge_count(), 0);
+        assert!(matches!(
+            db.try_eval(|db| try_ping(db, Time(0))),
+            Err(Error::CyclicDependency { .. })
+        ));
+    }
+
+    #[test]
+    fn cyclic_dependency_through_eval_many_is_reported() {
+        let db = test_db();
+        fn many_ping(db: &TestDb, i: i64, t: Time) -> f64 {
+            db.register_fn("many_ping", i, t, move |db| {
+                db.eval_many(&[(many_pong, i, t)])[0]
+            })
+        }
+        fn many_pong(db: &TestDb, i: i64, t: Time) -> f64 {
+            db.register_fn("many_pong", i, t, move |db| many_ping(db, i, t) + 1.0)
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let error = pool
+            .install(|| db.try_eval(|db| many_ping(db, 1, Time(0))))
+            .unwrap_err();
+        match &error {
+            Error::CyclicDependency { path } => assert_eq!(path.len(), 3),
+            _ => panic!("unexpected error: {}", error),
+        }
+        assert!(db.value_cache.read().unwrap().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cyclic dependency")]
+    fn cyclic_dependency_panics_without_try_eval() {
+        let db = test_db();
+        ping(&db, Time(0));
+    }
+
+    #[test]
+    fn recursion_over_time_is_not_a_cycle() {
+        let db = test_db();
+
+        fn cumulative(db: &TestDb, t: Time) -> f64 {
+            db.register_fn("cumulative", 1, t, move |db| {
+                if t.0 == 0 {
+                    db.get_value("a", 1, t)
+                } else {
+                    cumulative(db, Time(t.0 - 1)) + 1.0
+                }
+            })
+        }
+
+        assert_eq!(db.try_eval(|db| cumulative(db, Time(100))).unwrap(), 101.0);
+    }
+
+    #[test]
+    fn early_cutoff_stops_at_unchanged_values() {
+        let mut db = test_db();
+        db.set_early_cutoff(Some(1e-9));
+        let calls = Calls::default();
+
+        fn scaled_min(db: &TestDb, calls: &Calls) -> f64 {
+            let calls = calls.clone();
+            db.register_fn("scaled_min", 1, Time(0), move |db| {
+                calls.increment();
+                let min = db.register_fn("min", 1, Time(0), |db| {
+                    db.get_value("a", 1, Time(0)).min(db.get_value("b", 1, Time(0)))
+                });
+                10.0 * min
+            })
+        }
+        let sub = {
+            let calls = calls.clone();
+            db.subscribe(move |db| scaled_min(db, &calls))
+        };
+        assert_eq!(calls.get(), 1);
+
+        // Only the larger input moved, so `min` keeps its value.
+        let dirty_refs = db.update("b", &1, &Time(0), 5.0).unwrap();
+        assert_eq!(dirty_refs.len(), 1);
+        assert!(db.recompute_subscriptions(&dirty_refs).is_empty());
+        assert_eq!(calls.get(), 1);
+
+        // Changes within the tolerance are ignored.
+        assert!(db.update("a", &1, &Time(0), 1.0 + 1e-12).unwrap().is_empty());
+
+        let dirty_refs = db.update("a", &1, &Time(0), 0.5).unwrap();
+        assert_eq!(dirty_refs.len(), 3);
+        assert_eq!(calls.get(), 2);
+        let updates = db.recompute_subscriptions(&dirty_refs);
+        assert_eq!(updates, vec![(sub.id(), 10.0.into(), 5.0.into())]);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn early_cutoff_does_not_accumulate_drift() {
+        let mut db = test_db();
+        db.set_early_cutoff(Some(0.1));
+        let calls = Calls::default();
+
+        fn restored(db: &TestDb) -> f64 {
+            db.register_fn("restored", 1, Time(0), |db| {
+                let tenth = db.register_fn("tenth", 1, Time(0), |db| {
+                    db.get_value("b", 1, Time(0)) / 10.0
+                });
+                10.0 * tenth
+            })
+        }
+        sum(&db, &calls);
+        restored(&db);
+
+        // Each step is within the tolerance of the previous value.
+        for i in 1..=10 {
+            let a = 1.0 + 0.09 * i as f64;
+            db.update("a", &1, &Time(0), a).unwrap();
+            assert!((sum(&db, &calls) - (a + 2.0)).abs() <= 0.1, "step {}", i);
+        }
+        // `tenth` moves within the tolerance, `restored` by ten times as much.
+        for i in 1..=10 {
+            let b = 2.0 + 0.5 * i as f64;
+            db.update("b", &1, &Time(0), b).unwrap();
+            assert!((restored(&db) - b).abs() <= 1.0 + 1e-9, "step {}", i);
+        }
+    }
+
+    #[test]
+    fn missing_value_errors_are_cached_and_invalidated() {
+        let mut db = test_db();
+        let calls = Calls::default();
+
+        fn checked_sum(db: &TestDb, calls: &Calls) -> Result<f64, Error> {
+            let calls = calls.clone();
+            db.try_register_fn("checked_sum", 1, Time(0), move |db| {
+                calls.increment();
+                Ok(db.try_get_value("a", 1, Time(0))? + db.try_get_value("c", 1, Time(0))?)
+            })
+        }
+        fn doubled(db: &TestDb, calls: &Calls) -> Result<f64, Error> {
+            let calls = calls.clone();
+            db.try_register_fn("doubled", 1, Time(0), move |db| {
+                Ok(2.0 * checked_sum(db, &calls)?)
+            })
+        }
+
+        let error = doubled(&db, &calls).unwrap_err();
+        match &error {
+            Error::MissingValue { name, selector, time, call_chain } => {
+                assert_eq!((*name, selector.as_str(), *time), ("c", "1", Time(0)));
+                assert_eq!(call_chain.len(), 2);
+            }
+            _ => panic!("unexpected error: {}", error),
+        }
+        assert_eq!(
+            error.to_string(),
+            "Missing value: c(1, 1970-01-01 00:00:00) read by \
+             doubled(1, 1970-01-01 00:00:00) -> checked_sum(1, 1970-01-01 00:00:00)"
+        );
+        assert!(doubled(&db, &calls).is_err());
+        assert_eq!(calls.get(), 1);
+
+        let dirty_refs = db.update("c", &1, &Time(0), 3.0).unwrap();
+        assert_eq!(dirty_refs.len(), 3);
+        assert_eq!(doubled(&db, &calls).unwrap(), 8.0);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn typed_values_flow_through_functions_and_subscriptions() {
+        let mut db = test_db();
+        db.update("state", &1, &Time(0), 2_i64).unwrap();
+        db.update("fuel", &1, &Time(0), "coal").unwrap();
+
+        fn is_running(db: &TestDb) -> bool {
+            db.register_fn("is_running", 1, Time(0), |db| {
+                db.get_value_as::<i64>("state", 1, Time(0)) > 0
+                    && db.get_value_as::<String>("fuel", 1, Time(0)) != "none"
+            })
+        }
+        let sub = db.subscribe(is_running);
+        assert!(is_running(&db));
+
+        let dirty_refs = db.update("state", &1, &Time(0), 0_i64).unwrap();
+        let updates = db.recompute_subscriptions(&dirty_refs);
+        assert_eq!(updates, vec![(sub.id(), true.into(), false.into())]);
+
+        let error = db.try_get_value_as::<bool>("state", 1, Time(0)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unexpected type of state: actual: i64, expected: bool"
+        );
+    }
+
+    #[test]
+    fn scenario_overrides_values_without_touching_live_db() {
+        let mut db = test_db();
+        let calls = Calls::default();
+        let b_calls = Calls::default();
+
+        fn doubled_b(db: &TestDb, calls: &Calls) -> f64 {
+            let calls = calls.clone();
+            db.register_fn("doubled_b", 1, Time(0), move |db| {
+                calls.increment();
+                2.0 * db.get_value("b", 1, Time(0))
+            })
+        }
+        fn sum_of_doubles(db: &TestDb) -> f64 {
+            db.register_fn("sum_of_doubles", 1, Time(0), |db| {
+                2.0 * db.get_value("a", 1, Time(0)) + 2.0 * db.get_value("b", 1, Time(0))
+            })
+        }
+        assert_eq!(double_sum(&db, &calls), 6.0);
+        assert_eq!(doubled_b(&db, &b_calls), 4.0);
+
+        let mut scenario = db.scenario();
+        scenario.update("a", &1, &Time(0), 5.0).unwrap();
+        assert_eq!(double_sum(&scenario, &calls), 14.0);
+        assert_eq!(scenario.get_value("a", 1, Time(0)), 5.0);
+        // Not affected by the override, so the result of the live database is reused.
+        assert_eq!(doubled_b(&scenario, &b_calls), 4.0);
+        assert_eq!(b_calls.get(), 1);
+        assert_eq!(double_sum(&db, &calls), 6.0);
+        assert_eq!(calls.get(), 2);
+
+        // Evaluated by the live database after the override.
+        assert_eq!(sum_of_doubles(&db), 6.0);
+        assert_eq!(sum_of_doubles(&scenario), 14.0);
+        scenario.update("a", &1, &Time(0), 6.0).unwrap();
+        assert_eq!(doubled_b(&scenario, &b_calls), 4.0);
+        assert_eq!(sum_of_doubles(&scenario), 16.0);
+        scenario.discard();
+        assert_eq!(db.get_value("a", 1, Time(0)), 1.0);
+
+        let mut scenario = db.scenario();
+        scenario.update("b", &1, &Time(0), 3.0).unwrap();
+        let updates = scenario.into_updates();
+        db.update_many(updates).unwrap();
+        assert_eq!(double_sum(&db, &calls), 8.0);
+        assert_eq!(doubled_b(&db, &b_calls), 6.0);
+    }
+
+    #[test]
+    fn scenario_keeps_dependencies_of_live_db() {
+        let mut db = test_db();
+        db.update("c", &1, &Time(0), 5.0).unwrap();
+        fn pick(db: &TestDb) -> f64 {
+            db.register_fn("pick", 1, Time(0), |db| {
+                if db.get_value("b", 1, Time(0)) > 1.5 {
+                    db.get_value("a", 1, Time(0))
+                } else {
+                    db.get_value("c", 1, Time(0))
+                }
+            })
+        }
+        assert_eq!(pick(&db), 1.0);
+
+        let mut scenario = db.scenario();
+        scenario.update("b", &1, &Time(0), 1.0).unwrap();
+        assert_eq!(pick(&scenario), 5.0);
+        scenario.discard();
+
+        db.update("a", &1, &Time(0), 3.0).unwrap();
+        assert_eq!(pick(&db), 3.0);
+    }
+
+    #[test]
+    fn as_of_evaluates_with_values_known_at_the_time() {
+        let mut db = test_db();
+        db.enable_history();
+        let calls = Calls::default();
+
+        db.update_many_known_at([("a", 1, Time(0), 5.0)], Time(100)).unwrap();
+        db.update_many_known_at([("a", 1, Time(0), 7.0), ("c", 1, Time(0), 1.0)], Time(200))
+            .unwrap();
+        assert_eq!(double_sum(&db, &calls), 18.0);
+
+        assert_eq!(double_sum(&db.as_of(Time(50)), &calls), 6.0);
+        assert_eq!(double_sum(&db.as_of(Time(100)), &calls), 14.0);
+        assert_eq!(double_sum(&db.as_of(Time(150)), &calls), 14.0);
+        assert_eq!(double_sum(&db.as_of(Time(250)), &calls), 18.0);
+
+        let past = db.as_of(Time(150));
+        assert_eq!(past.get_value_opt("c", 1, Time(0)), None);
+        assert!(past.try_get_value("c", 1, Time(0)).is_err());
+        std::mem::drop(past);
+        assert_eq!(db.get_value_opt("c", 1, Time(0)), Some(1.0));
+    }
+
+    #[test]
+    fn applied_transactions_advance_last_lsn() {
+        // The relation of the insert was never described, so applying it fails.
+        let undescribed = || {
+            vec![message(
+                b'I',
+                &[&1_u32.to_be_bytes(), b"N", &0_u16.to_be_bytes()],
+            )]
+        };
+
+        let mut db = test_db();
+        db.last_lsn = Some(100);
+        // Already applied, e.g. in the snapshot, so the undescribed relation is not reached.
+        assert!(db.apply_changes(transaction(100, undescribed())).is_ok());
+        assert_eq!(db.last_lsn(), Some(100));
+
+        let mut changes = transaction(150, Vec::new());
+        changes.extend(transaction(200, undescribed()));
+        assert!(db.apply_changes(changes).is_err());
+        assert_eq!(db.last_lsn(), Some(150));
+    }
+
+    #[test]
+    fn skipped_transactions_describe_relations() {
+        let insert = |a: &str| {
+            message(
+                b'I',
+                &[&TABLE_OID.to_be_bytes(), b"N", &3_u16.to_be_bytes(), b"n", &text(a), &text("1")],
+            )
+        };
+
+        let mut db = test_db();
+        db.last_lsn = Some(100);
+        let mut changes = transaction(100, vec![table_relation(), insert("4.0")]);
+        changes.extend(transaction(200, vec![insert("6.0")]));
+        db.apply_changes(changes).unwrap();
+        assert_eq!(db.last_lsn(), Some(200));
+        assert_eq!(db.get_value("a", 1, Time(0)), 6.0);
+
+        // Skipped on the server, only the relation is left.
+        let mut db = test_db();
+        db.last_lsn = Some(100);
+        let mut changes = vec![table_relation()];
+        changes.extend(transaction(200, vec![insert("6.0")]));
+        db.apply_changes(changes).unwrap();
+        assert_eq!(db.get_value("a", 1, Time(0)), 6.0);
+    }
+
+    const TABLE_OID: u32 = 16385;
+
+    /// Replication message with the given tag, encoded as by pgoutput.
+    fn message(tag: u8, body: &[&[u8]]) -> LogicalReplicationMessage {
+        let mut message = vec![tag];
+        for part in body {
+            message.extend_from_slice(part);
+        }
+        pgoutput::decode(&message).unwrap()
+    }
+
+    fn text(value: &str) -> Vec<u8> {
+        let mut column = vec![b't'];
+        column.extend((value.len() as u32).to_be_bytes());
+        column.extend(value.as_bytes());
+        column
+    }
+
+    fn column(name: &str, type_oid: u32) -> Vec<u8> {
+        let mut column = vec![0];
+        column.extend(name.as_bytes());
+        column.push(0);
+        column.extend(type_oid.to_be_bytes());
+        column.extend((-1_i32).to_be_bytes());
+        column
+    }
+
+    /// Relation of `TestTable`. The columns are looked up by name, in any order.
+    fn table_relation() -> LogicalReplicationMessage {
+        message(
+            b'R',
+            &[
+                &TABLE_OID.to_be_bytes(),
+                b"public\0Table\0f",
+                &3_u16.to_be_bytes(),
+                &column("comment", 25),
+                &column("a", 701),
+                &column("selector", 20),
+            ],
+        )
+    }
+
+    /// Transaction committed at `end_lsn` with the given changes.
+    fn transaction(
+        end_lsn: u64,
+        changes: Vec<LogicalReplicationMessage>,
+    ) -> Vec<LogicalReplicationMessage> {
+        use crate::replication::pgoutput::{MessageBegin, MessageCommit};
+
+        let commit_timestamp = chrono::DateTime::from_timestamp(0, 0).unwrap();
+        let mut messages = vec![LogicalReplicationMessage::Begin(MessageBegin {
+            final_lsn: end_lsn,
+            commit_timestamp,
+            transaction_id: 1,
+        })];
+        messages.extend(changes);
+        messages.push(LogicalReplicationMessage::Commit(MessageCommit {
+            flags: 0,
+            lsn: end_lsn,
+            end_lsn,
+            commit_timestamp,
+        }));
+        messages
+    }
+
+    #[test]
+    fn failed_transaction_keeps_updates_of_applied_ones() {
+        let mut db = test_db();
+        let sub = db.subscribe(|db| db.get_value("a", 1, Time(0)));
+
+        let insert = message(
+            b'I',
+            &[&TABLE_OID.to_be_bytes(), b"N", &3_u16.to_be_bytes(), b"n", &text("4.0"), &text("1")],
+        );
+        // The relation of the second transaction was never described.
+        let undescribed = message(
+            b'I',
+            &[&1_u32.to_be_bytes(), b"N", &1_u16.to_be_bytes(), &text("1")],
+        );
+        let mut changes = transaction(100, vec![table_relation(), insert]);
+        changes.extend(transaction(200, vec![undescribed]));
+
+        assert!(db.apply_changes(changes).is_err());
+        assert_eq!(db.last_lsn(), Some(100));
+        assert_eq!(db.get_value("a", 1, Time(0)), 4.0);
+        assert_eq!(
+            db.apply_changes(Vec::new()).unwrap(),
+            vec![(sub.id(), 1.0.into(), 4.0.into())]
+        );
+        assert!(db.apply_changes(Vec::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_transaction_changes_nothing() {
+        let mut db = test_db();
+        let insert = |a: &str| {
+            message(
+                b'I',
+                &[&TABLE_OID.to_be_bytes(), b"N", &3_u16.to_be_bytes(), b"n", &text(a), &text("1")],
+            )
+        };
+        // Tables that are not replicated into the database are skipped.
+        let other_relation = message(
+            b'R',
+            &[&16386_u32.to_be_bytes(), b"public\0Other\0f", &1_u16.to_be_bytes(), &column("x", 20)],
+        );
+        let other_insert = message(
+            b'I',
+            &[&16386_u32.to_be_bytes(), b"N", &1_u16.to_be_bytes(), &text("5")],
+        );
+        let changes = transaction(
+            100,
+            vec![table_relation(), other_relation, other_insert, insert("4.0")],
+        );
+        db.apply_changes(changes).unwrap();
+        assert_eq!(db.get_value("a", 1, Time(0)), 4.0);
+
+        let changes = transaction(200, vec![insert("6.0"), insert("six")]);
+        assert!(db.apply_changes(changes).is_err());
+        assert_eq!(db.last_lsn(), Some(100));
+        assert_eq!(db.get_value("a", 1, Time(0)), 4.0);
+    }
+
+    #[test]
+    fn deleted_cleared_and_truncated_values_invalidate_dependents() {
+        let relation = table_relation();
+
+        let mut db = test_db();
+        let calls = Calls::default();
+        fn checked_a(db: &TestDb, calls: &Calls) -> Result<f64, Error> {
+            let calls = calls.clone();
+            db.try_register_fn("checked_a", 1, Time(0), move |db| {
+                calls.increment();
+                db.try_get_value("a", 1, Time(0))
+            })
+        }
+        assert_eq!(checked_a(&db, &calls).unwrap(), 1.0);
+
+        let delete = message(
+            b'D',
+            &[&TABLE_OID.to_be_bytes(), b"O", &3_u16.to_be_bytes(), b"n", &text("1.0"), &text("1")],
+        );
+        let dirty_refs = db.apply_transaction(&[relation, delete], Time(10)).unwrap();
+        assert_eq!(dirty_refs.len(), 2);
+        assert!(db.value_provider().get_value_opt("a", &1, &Time(0)).is_none());
+        assert!(checked_a(&db, &calls).is_err());
+        assert_eq!(calls.get(), 2);
+
+        db.update("a", &1, &Time(0), 5.0).unwrap();
+        assert_eq!(checked_a(&db, &calls).unwrap(), 5.0);
+
+        // NULL clears the value.
+        let update = message(
+            b'U',
+            &[&TABLE_OID.to_be_bytes(), b"N", &3_u16.to_be_bytes(), b"n", b"n", &text("1")],
+        );
+        let dirty_refs = db.apply_transaction(&[update], Time(15)).unwrap();
+        assert_eq!(dirty_refs.len(), 2);
+        assert!(checked_a(&db, &calls).is_err());
+        db.update("a", &1, &Time(0), 5.0).unwrap();
+
+        let truncate = message(b'T', &[&1_u32.to_be_bytes(), &[0], &TABLE_OID.to_be_bytes()]);
+        // The relation is described only once per replication session.
+        let dirty_refs = db.apply_transaction(&[truncate], Time(20)).unwrap();
+        assert_eq!(dirty_refs.len(), 2);
+        assert!(db.value_provider().get_value_opt("b", &1, &Time(0)).is_none());
+        assert!(checked_a(&db, &calls).is_err());
+    }
+
+    #[test]
+    fn rows_are_removed_from_their_key() {
+        let mut db = test_db();
+        db.update("a", &2, &Time(0), 2.0).unwrap();
+        // Only the selector is part of the key, the other columns are NULL.
+        let key = |selector: &str| [b"K", &3_u16.to_be_bytes()[..], b"nn", &text(selector)].concat();
+
+        // The selector of the row changed from 1 to 3.
+        let update = message(
+            b'U',
+            &[&TABLE_OID.to_be_bytes(), &key("1"), b"N", &3_u16.to_be_bytes(), b"n", &text("4.0"), &text("3")],
+        );
+        db.apply_transaction(&[table_relation(), update], Time(10)).unwrap();
+        assert!(db.value_provider().get_value_opt("a", &1, &Time(0)).is_none());
+        assert_eq!(db.get_value("a", 3, Time(0)), 4.0);
+
+        // Without `REPLICA IDENTITY FULL`, only the key of a deleted row is replicated.
+        let delete = message(b'D', &[&TABLE_OID.to_be_bytes(), &key("2")]);
+        db.apply_transaction(&[delete], Time(20)).unwrap();
+        assert!(db.value_provider().get_value_opt("a", &2, &Time(0)).is_none());
+        assert_eq!(db.get_value("a", 3, Time(0)), 4.0);
+    }
+
+    #[test]
+    fn range_update_recomputes_only_affected_slices() {
+        let mut db = test_db();
+        let calls = Calls::default();
+        for t in 0..4 {
+            db.update("a", &1, &Time::from_timestamp(t), t as f64).unwrap();
+        }
+
+        fn doubled(db: &TestDb, calls: &Calls, t: Time) -> f64 {
+            let calls = calls.clone();
+            db.register_fn("doubled", 1, t, move |db| {
+                calls.increment();
+                2.0 * db.get_value("a", 1, t)
+            })
         }
+        let (start, end) = (Time::from_timestamp(0), Time::from_timestamp(4));
+        let range = TimeRange::new(start, end, chrono::Duration::seconds(1));
+        let profile = db.eval_range(doubled, &calls, &range);
+        assert_eq!(profile.values(), &[0.0, 2.0, 4.0, 6.0]);
+        assert_eq!(profile.get(&Time::from_timestamp(2)), Some(4.0));
+        assert_eq!(calls.get(), 4);
 
-        updated_subscribers
+        let sub = db.subscribe({
+            let calls = calls.clone();
+            move |db| db.eval_range(doubled, &calls, &range).values().iter().sum::<f64>()
+        });
+        let dirty_refs = db.update("a", &1, &Time::from_timestamp(2), 5.0).unwrap();
+        assert_eq!(dirty_refs.len(), 2);
+        let updates = db.recompute_subscriptions(&dirty_refs);
+        assert_eq!(updates, vec![(sub.id(), 12.0.into(), 18.0.into())]);
+        assert_eq!(calls.get(), 5);
     }
 }
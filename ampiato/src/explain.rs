@@ -0,0 +1,52 @@
+use crate::core::{defs::Time, value::Value};
+
+/// How a value was computed, as returned by [`Db::explain`](crate::Db::explain).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation<Sel> {
+    pub name: &'static str,
+    pub selector: Sel,
+    pub time: Time,
+    /// `None` when the source value is missing or the function result is no longer cached.
+    pub value: Option<Value>,
+    /// Table and column the value was read from; `None` for function results and values
+    /// of quantities without a known source.
+    pub source: Option<(&'static str, &'static str)>,
+    /// Values the function result was computed from.
+    pub inputs: Vec<Explanation<Sel>>,
+}
+
+impl<Sel> Explanation<Sel> {
+    /// All the source table values the result depends on, depth first.
+    pub fn leaves(&self) -> Vec<&Explanation<Sel>> {
+        if self.inputs.is_empty() {
+            return vec![self];
+        }
+        self.inputs.iter().flat_map(|input| input.leaves()).collect()
+    }
+
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result
+    where
+        Sel: std::fmt::Debug,
+    {
+        write!(f, "{:indent$}", "", indent = 2 * depth)?;
+        match self.source {
+            Some((table, column)) => write!(f, "{}.{}", table, column)?,
+            None => write!(f, "{}", self.name)?,
+        }
+        write!(f, "({:?}, {:?}) = ", self.selector, self.time)?;
+        match &self.value {
+            Some(value) => writeln!(f, "{}", value)?,
+            None => writeln!(f, "?")?,
+        }
+        for input in self.inputs.iter() {
+            input.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl<Sel: std::fmt::Debug> std::fmt::Display for Explanation<Sel> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
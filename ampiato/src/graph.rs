@@ -0,0 +1,266 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use petgraph::graph::Graph;
+use petgraph::stable_graph::StableGraph;
+use petgraph::visit::{EdgeRef as _, IntoEdgeReferences as _, NodeIndexable as _};
+use petgraph::Direction;
+
+use crate::core::defs::{Index, Time};
+
+pub type NodeT<Sel> = (&'static str, Sel, Time);
+
+/// Dependency graph of the computation.
+///
+/// Nodes are the values read from the value provider and the results of `#[tem_fn]`
+/// functions. An edge `a -> b` means that `b` was computed from `a`.
+///
+/// The graph is stored as adjacency lists, so the memory grows with the number of edges
+/// instead of quadratically with the number of nodes. Removing a node keeps the indices
+/// of all other nodes valid; use [`DepGraph::compact`] to reclaim the freed slots.
+#[derive(Debug, Clone)]
+pub struct DepGraph<Sel> {
+    graph: StableGraph<NodeT<Sel>, (), petgraph::Directed, usize>,
+}
+
+impl<Sel> Default for DepGraph<Sel> {
+    fn default() -> Self {
+        DepGraph {
+            graph: StableGraph::default(),
+        }
+    }
+}
+
+impl<Sel> DepGraph<Sel> {
+    pub fn add_node(&mut self, node: NodeT<Sel>) -> Index {
+        self.graph.add_node(node)
+    }
+
+    /// Remove the node together with all its edges.
+    pub fn remove_node(&mut self, index: Index) -> Option<NodeT<Sel>> {
+        self.graph.remove_node(index)
+    }
+
+    /// Add an edge from `dependency` to `dependent`, unless it already exists.
+    pub fn update_edge(&mut self, dependency: Index, dependent: Index) {
+        self.graph.update_edge(dependency, dependent, ());
+    }
+
+    /// Remove the edges from all the nodes the given node was computed from.
+    pub fn clear_dependencies(&mut self, index: Index) {
+        let edges: Vec<_> = self
+            .graph
+            .edges_directed(index, Direction::Incoming)
+            .map(|e| e.id())
+            .collect();
+        for edge in edges {
+            self.graph.remove_edge(edge);
+        }
+    }
+
+    pub fn node_weight(&self, index: Index) -> Option<&NodeT<Sel>> {
+        self.graph.node_weight(index)
+    }
+
+    pub fn contains_node(&self, index: Index) -> bool {
+        self.graph.contains_node(index)
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.graph.node_count()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.graph.edge_count()
+    }
+
+    pub fn node_indices(&self) -> impl Iterator<Item = Index> + '_ {
+        self.graph.node_indices()
+    }
+
+    /// All edges as `(dependency, dependent)` pairs.
+    pub fn edges(&self) -> impl Iterator<Item = (Index, Index)> + '_ {
+        self.graph
+            .edge_references()
+            .map(|e| (e.source(), e.target()))
+    }
+
+    /// Nodes the given node was computed from.
+    pub fn dependencies(&self, index: Index) -> impl Iterator<Item = Index> + '_ {
+        self.graph.neighbors_directed(index, Direction::Incoming)
+    }
+
+    /// Nodes computed from the given node.
+    pub fn dependents(&self, index: Index) -> impl Iterator<Item = Index> + '_ {
+        self.graph.neighbors_directed(index, Direction::Outgoing)
+    }
+
+    /// Number of node slots, including the ones freed by removed nodes.
+    pub fn capacity(&self) -> usize {
+        self.graph.node_bound()
+    }
+
+    /// Renumber the nodes so that there are no holes left by removed nodes.
+    ///
+    /// Returns the mapping from old to new indices. All indices held outside of the graph
+    /// must be translated with it.
+    pub fn compact(&mut self) -> HashMap<Index, Index> {
+        // Converting to `Graph` closes the holes, keeping the relative order of the nodes.
+        let old_indices: Vec<Index> = self.graph.node_indices().collect();
+        let graph: Graph<NodeT<Sel>, (), petgraph::Directed, usize> =
+            std::mem::take(&mut self.graph).into();
+        self.graph = graph.into();
+
+        old_indices
+            .into_iter()
+            .enumerate()
+            .map(|(new_index, old_index)| (old_index, Index::new(new_index)))
+            .collect()
+    }
+}
+
+/// Part of the dependency graph selected for export.
+#[derive(Debug, Clone)]
+pub enum GraphScope<Sel> {
+    /// The whole graph.
+    All,
+    /// The node and everything it was computed from.
+    Upstream(NodeT<Sel>),
+    /// The node and everything computed from it.
+    Downstream(NodeT<Sel>),
+}
+
+impl<Sel> DepGraph<Sel> {
+    /// The given node and all nodes reachable from it in the given direction.
+    pub fn cone(&self, index: Index, direction: Direction) -> HashSet<Index> {
+        let mut cone = HashSet::new();
+        let mut stack = vec![index];
+        while let Some(index) = stack.pop() {
+            if self.contains_node(index) && cone.insert(index) {
+                stack.extend(self.graph.neighbors_directed(index, direction));
+            }
+        }
+        cone
+    }
+}
+
+impl<Sel: std::fmt::Debug> DepGraph<Sel> {
+    /// Render the given nodes and the edges between them in the Graphviz DOT format.
+    ///
+    /// With `nodes == None` the whole graph is rendered.
+    pub fn to_dot(&self, nodes: Option<&HashSet<Index>>) -> String {
+        let mut dot = String::from("digraph ampiato {\n");
+        for index in self.exported_nodes(nodes) {
+            let label = node_label(&self.graph[index])
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"");
+            writeln!(dot, "    n{} [label=\"{}\"];", index.index(), label).unwrap();
+        }
+        for (from, to) in self.exported_edges(nodes) {
+            writeln!(dot, "    n{} -> n{};", from.index(), to.index()).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the given nodes and the edges between them as a JSON node/edge list.
+    ///
+    /// With `nodes == None` the whole graph is rendered.
+    pub fn to_json(&self, nodes: Option<&HashSet<Index>>) -> serde_json::Value {
+        let json_nodes: Vec<_> = self
+            .exported_nodes(nodes)
+            .map(|index| {
+                let (name, selector, t) = &self.graph[index];
+                serde_json::json!({
+                    "id": index.index(),
+                    "name": name,
+                    "selector": format!("{:?}", selector),
+                    "time": format!("{:?}", t),
+                    "label": node_label(&self.graph[index]),
+                })
+            })
+            .collect();
+        let json_edges: Vec<_> = self
+            .exported_edges(nodes)
+            .map(|(from, to)| serde_json::json!({ "from": from.index(), "to": to.index() }))
+            .collect();
+        serde_json::json!({ "nodes": json_nodes, "edges": json_edges })
+    }
+
+    fn exported_nodes<'a>(
+        &'a self,
+        nodes: Option<&'a HashSet<Index>>,
+    ) -> impl Iterator<Item = Index> + 'a {
+        self.node_indices()
+            .filter(move |index| nodes.is_none_or(|nodes| nodes.contains(index)))
+    }
+
+    fn exported_edges<'a>(
+        &'a self,
+        nodes: Option<&'a HashSet<Index>>,
+    ) -> impl Iterator<Item = (Index, Index)> + 'a {
+        self.edges().filter(move |(from, to)| {
+            nodes.is_none_or(|nodes| nodes.contains(from) && nodes.contains(to))
+        })
+    }
+}
+
+pub(crate) fn node_label<Sel: std::fmt::Debug>((name, selector, t): &NodeT<Sel>) -> String {
+    format!("{}({:?}, {:?})", name, selector, t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_keeps_nodes_and_edges() {
+        let mut graph = DepGraph::<()>::default();
+        let a = graph.add_node(("a", (), Time(0)));
+        let b = graph.add_node(("b", (), Time(0)));
+        let c = graph.add_node(("c", (), Time(0)));
+        graph.update_edge(a, c);
+        graph.update_edge(b, c);
+        graph.update_edge(b, c);
+        assert_eq!(graph.edge_count(), 2);
+
+        graph.remove_node(a);
+        assert_eq!(graph.capacity(), 3);
+
+        let remap = graph.compact();
+        assert_eq!(graph.capacity(), 2);
+        assert_eq!(graph.node_weight(remap[&c]), Some(&("c", (), Time(0))));
+        assert_eq!(
+            graph.dependencies(remap[&c]).collect::<Vec<_>>(),
+            vec![remap[&b]]
+        );
+    }
+
+    #[test]
+    fn export_upstream_cone() {
+        let mut graph = DepGraph::<i64>::default();
+        let a = graph.add_node(("a", 1, Time(0)));
+        let b = graph.add_node(("b", 1, Time(0)));
+        let c = graph.add_node(("c", 1, Time(0)));
+        graph.update_edge(a, b);
+        graph.update_edge(b, c);
+
+        let cone = graph.cone(b, Direction::Incoming);
+        assert_eq!(cone, HashSet::from([a, b]));
+
+        let dot = graph.to_dot(Some(&cone));
+        assert_eq!(
+            dot,
+            "digraph ampiato {\n    \
+             n0 [label=\"a(1, 1970-01-01 00:00:00)\"];\n    \
+             n1 [label=\"b(1, 1970-01-01 00:00:00)\"];\n    \
+             n0 -> n1;\n\
+             }\n"
+        );
+
+        let json = graph.to_json(None);
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 3);
+        assert_eq!(json["nodes"][2]["name"], "c");
+        assert_eq!(json["edges"][1], serde_json::json!({ "from": 1, "to": 2 }));
+    }
+}
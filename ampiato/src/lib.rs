@@ -2,19 +2,30 @@
 pub mod ast;
 pub mod core;
 mod db;
+mod explain;
+mod graph;
 pub mod replication;
+mod scenario;
+mod shared_db;
+mod snapshot;
 mod ts;
 mod value_provider;
 pub mod prelude;
 
 // Reeexported modules
+pub use chrono::Duration;
 
 // Ampiato modules
-pub use crate::core::defs::Time;
+pub use crate::core::defs::{Time, TimeRange};
+pub use crate::core::value::{Value, ValueType};
 pub use crate::core::{Error, TableMetadata, TableValues};
-pub use crate::replication::FromTupleData;
+pub use crate::replication::{FromTupleData, ReplicationConfig, ReplicationSlot, ReplicationStream};
 
-pub use db::Db;
+pub use db::{Db, Subscription, SubscriptionId};
+pub use explain::Explanation;
+pub use graph::{DepGraph, GraphScope, NodeT};
+pub use scenario::Scenario;
+pub use shared_db::SharedDb;
 pub use ts::{TimeSeriesChanges, TimeSeriesDense, TimeSeriesInterval};
 pub use value_provider::ValueProvider;
 
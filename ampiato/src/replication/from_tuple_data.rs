@@ -1,11 +1,170 @@
 use crate::core::Error;
 
-use crate::replication::pgoutput::TupleData;
+use crate::replication::pgoutput::{ColumnValue, Decode, ParseError, TupleData};
+use crate::replication::relation::Relation;
+
+/// Tuple of a replicated row, with the columns looked up by name in its relation.
+///
+/// Unchanged TOAST values of an updated row are taken from its old tuple, which is
+/// replicated for tables with `REPLICA IDENTITY FULL`. The ones that cannot be resolved
+/// are decoded as NULL and reported by [`TupleRow::is_unchanged`], so that the current
+/// values are kept.
+pub struct TupleRow<'a> {
+    relation: &'a Relation,
+    tuple_data: &'a TupleData,
+    old_tuple: Option<&'a TupleData>,
+}
+
+impl<'a> TupleRow<'a> {
+    pub fn new(
+        relation: &'a Relation,
+        tuple_data: &'a TupleData,
+        old_tuple: Option<&'a TupleData>,
+    ) -> Result<Self, Error> {
+        for tuple_data in std::iter::once(tuple_data).chain(old_tuple) {
+            if tuple_data.columns.len() != relation.columns.len() {
+                return Err(Error::UnexpectedNumberOfColumns {
+                    actual: tuple_data.columns.len(),
+                    expected: relation.columns.len(),
+                });
+            }
+        }
+        Ok(TupleRow {
+            relation,
+            tuple_data,
+            old_tuple,
+        })
+    }
+
+    pub fn relation_name(&self) -> &str {
+        &self.relation.name
+    }
+
+    pub fn column(&self, name: &str) -> Result<&'a ColumnValue, Error> {
+        match self.relation.column_index(name) {
+            Some(index) => match (&self.tuple_data.columns[index], self.old_tuple) {
+                (ColumnValue::UnchangedToast, Some(old_tuple)) => Ok(&old_tuple.columns[index]),
+                (value, _) => Ok(value),
+            },
+            None => Err(Error::UnknownColumn {
+                table_name: self.relation.name.clone(),
+                column_name: name.to_string(),
+            }),
+        }
+    }
+
+    /// Whether the column is an unchanged TOAST value missing in the old tuple.
+    pub fn is_unchanged(&self, name: &str) -> bool {
+        matches!(self.column(name), Ok(ColumnValue::UnchangedToast))
+    }
+
+    /// Decode the column by the type it has in the relation.
+    pub fn decode<T: Decode>(&self, name: &str) -> Result<T, Error> {
+        let value = self.column(name)?;
+        // The column exists, as it was just looked up.
+        let column = &self.relation.columns[self.relation.column_index(name).unwrap()];
+        let decoded = match value {
+            ColumnValue::UnchangedToast => T::decode(&ColumnValue::Null, column.type_oid)
+                .map_err(|_| ParseError::UnchangedToast),
+            _ => T::decode(value, column.type_oid),
+        };
+        decoded.map_err(|error| {
+            Error::ReplicationError(format!(
+                "Invalid value of {}.{} ({}): {:?}",
+                self.relation.name, name, column.type_name, error
+            ))
+        })
+    }
+}
 
 pub trait FromTupleData: Sized {
-    fn from_tuple_data(tuple_data: &TupleData) -> Result<Self, Error>;
+    fn from_tuple_data(row: &TupleRow) -> Result<Self, Error>;
 }
 
 pub trait TableFromTupleData: Sized {
-    fn from_tuple_data(relation_name: &str, tuple_data: &TupleData) -> Result<Self, Error>;
+    fn from_tuple_data(row: &TupleRow) -> Result<Self, Error>;
+
+    /// Names of the tables the values are replicated from.
+    fn table_names() -> Vec<&'static str>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replication::{oid, relation::RelationColumn};
+
+    #[test]
+    fn columns_are_looked_up_by_name() {
+        let relation = Relation {
+            oid: 16385,
+            namespace: "public".to_string(),
+            name: "Market".to_string(),
+            columns: ["id", "cEle", "CzkEur"]
+                .into_iter()
+                .map(|name| RelationColumn {
+                    name: name.to_string(),
+                    type_oid: 701,
+                    type_name: oid::type_name(701),
+                })
+                .collect(),
+        };
+        let text = |value: &str| ColumnValue::Text {
+            length: value.len() as u32,
+            data: value.as_bytes().to_vec(),
+        };
+        let tuple_data = TupleData {
+            number_of_columns: 3,
+            columns: vec![text("7"), text("50.5"), text("25.1")],
+        };
+
+        let row = TupleRow::new(&relation, &tuple_data, None).unwrap();
+        assert_eq!(row.decode::<f64>("CzkEur").unwrap(), 25.1);
+        assert_eq!(row.decode::<f64>("cEle").unwrap(), 50.5);
+        assert_eq!(
+            row.decode::<f64>("pInst").unwrap_err().to_string(),
+            "Unknown column: Market.pInst"
+        );
+
+        let tuple_data = TupleData {
+            number_of_columns: 2,
+            columns: vec![text("7"), text("50.5")],
+        };
+        assert!(TupleRow::new(&relation, &tuple_data, None).is_err());
+    }
+
+    #[test]
+    fn unchanged_toast_values_are_taken_from_the_old_tuple() {
+        let relation = Relation {
+            oid: 16385,
+            namespace: "public".to_string(),
+            name: "BlokDef".to_string(),
+            columns: ["Jmeno", "Barva"]
+                .into_iter()
+                .map(|name| RelationColumn {
+                    name: name.to_string(),
+                    type_oid: 25,
+                    type_name: oid::type_name(25),
+                })
+                .collect(),
+        };
+        let text = |value: &str| ColumnValue::Text {
+            length: value.len() as u32,
+            data: value.as_bytes().to_vec(),
+        };
+        let new_tuple = TupleData {
+            number_of_columns: 2,
+            columns: vec![ColumnValue::UnchangedToast, ColumnValue::UnchangedToast],
+        };
+        let old_tuple = TupleData {
+            number_of_columns: 2,
+            columns: vec![text("B1"), ColumnValue::UnchangedToast],
+        };
+
+        let row = TupleRow::new(&relation, &new_tuple, Some(&old_tuple)).unwrap();
+        assert_eq!(row.decode::<String>("Jmeno").unwrap(), "B1");
+        assert!(!row.is_unchanged("Jmeno"));
+        assert!(row.is_unchanged("Barva"));
+        assert_eq!(row.decode::<Option<String>>("Barva").unwrap(), None);
+        assert!(row.decode::<String>("Barva").is_err());
+    }
 }
@@ -1,7 +1,14 @@
+pub mod oid;
 pub mod pgoutput;
 pub mod print;
+#[allow(clippy::module_inception)]
 pub mod replication;
 pub mod from_tuple_data;
+pub mod relation;
+pub mod stream;
 
 pub use print::{print_replication_slots, print_publications};
-pub use from_tuple_data::{FromTupleData, TableFromTupleData};
\ No newline at end of file
+pub use from_tuple_data::{FromTupleData, TableFromTupleData, TupleRow};
+pub use relation::{Relation, RelationCache, RelationColumn};
+pub use replication::{ReplicationConfig, ReplicationSlot};
+pub use stream::ReplicationStream;
\ No newline at end of file
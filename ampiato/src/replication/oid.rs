@@ -0,0 +1,97 @@
+//! OIDs of the built-in PostgreSQL types, as listed in `pg_type`.
+//!
+//! The columns of a `Relation` message carry the OID of their type, which selects how the
+//! values are decoded, see [`Decode`](crate::replication::pgoutput::Decode). Types that
+//! are not built in are described by `Type` messages before the relations using them.
+
+pub const BOOL: u32 = 16;
+pub const BYTEA: u32 = 17;
+pub const NAME: u32 = 19;
+pub const INT8: u32 = 20;
+pub const INT2: u32 = 21;
+pub const INT4: u32 = 23;
+pub const TEXT: u32 = 25;
+pub const OID: u32 = 26;
+pub const JSON: u32 = 114;
+pub const FLOAT4: u32 = 700;
+pub const FLOAT8: u32 = 701;
+pub const BPCHAR: u32 = 1042;
+pub const VARCHAR: u32 = 1043;
+pub const DATE: u32 = 1082;
+pub const TIMESTAMP: u32 = 1114;
+pub const TIMESTAMPTZ: u32 = 1184;
+pub const INTERVAL: u32 = 1186;
+pub const NUMERIC: u32 = 1700;
+pub const UUID: u32 = 2950;
+pub const JSONB: u32 = 3802;
+
+pub const BOOL_ARRAY: u32 = 1000;
+pub const INT2_ARRAY: u32 = 1005;
+pub const INT4_ARRAY: u32 = 1007;
+pub const TEXT_ARRAY: u32 = 1009;
+pub const BPCHAR_ARRAY: u32 = 1014;
+pub const VARCHAR_ARRAY: u32 = 1015;
+pub const INT8_ARRAY: u32 = 1016;
+pub const FLOAT4_ARRAY: u32 = 1021;
+pub const FLOAT8_ARRAY: u32 = 1022;
+pub const TIMESTAMP_ARRAY: u32 = 1115;
+pub const DATE_ARRAY: u32 = 1182;
+pub const TIMESTAMPTZ_ARRAY: u32 = 1185;
+pub const INTERVAL_ARRAY: u32 = 1187;
+pub const NUMERIC_ARRAY: u32 = 1231;
+pub const UUID_ARRAY: u32 = 2951;
+pub const JSONB_ARRAY: u32 = 3807;
+
+/// Element type of a built-in array type.
+pub fn array_element_type(type_oid: u32) -> Option<u32> {
+    Some(match type_oid {
+        BOOL_ARRAY => BOOL,
+        INT2_ARRAY => INT2,
+        INT4_ARRAY => INT4,
+        TEXT_ARRAY => TEXT,
+        BPCHAR_ARRAY => BPCHAR,
+        VARCHAR_ARRAY => VARCHAR,
+        INT8_ARRAY => INT8,
+        FLOAT4_ARRAY => FLOAT4,
+        FLOAT8_ARRAY => FLOAT8,
+        TIMESTAMP_ARRAY => TIMESTAMP,
+        DATE_ARRAY => DATE,
+        TIMESTAMPTZ_ARRAY => TIMESTAMPTZ,
+        INTERVAL_ARRAY => INTERVAL,
+        NUMERIC_ARRAY => NUMERIC,
+        UUID_ARRAY => UUID,
+        JSONB_ARRAY => JSONB,
+        _ => return None,
+    })
+}
+
+/// Name of a built-in type, the OID itself for the other types.
+pub fn type_name(type_oid: u32) -> String {
+    let name = match type_oid {
+        BOOL => "bool",
+        BYTEA => "bytea",
+        NAME => "name",
+        INT8 => "int8",
+        INT2 => "int2",
+        INT4 => "int4",
+        TEXT => "text",
+        OID => "oid",
+        JSON => "json",
+        FLOAT4 => "float4",
+        FLOAT8 => "float8",
+        BPCHAR => "bpchar",
+        VARCHAR => "varchar",
+        DATE => "date",
+        TIMESTAMP => "timestamp",
+        TIMESTAMPTZ => "timestamptz",
+        INTERVAL => "interval",
+        NUMERIC => "numeric",
+        UUID => "uuid",
+        JSONB => "jsonb",
+        _ => match array_element_type(type_oid) {
+            Some(element_type) => return format!("{}[]", type_name(element_type)),
+            None => return type_oid.to_string(),
+        },
+    };
+    name.to_string()
+}
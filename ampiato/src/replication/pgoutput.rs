@@ -1,4 +1,3 @@
-use core::panic;
 use std::num::ParseFloatError;
 use std::num::ParseIntError;
 use std::str::from_utf8;
@@ -10,12 +9,16 @@ use binrw::NullString;
 use byteorder::BigEndian;
 use byteorder::ByteOrder;
 use chrono::DateTime;
+use chrono::NaiveDate;
 use chrono::NaiveDateTime;
 use chrono::Utc;
+use uuid::Uuid;
 
 use crate::core::defs::Time;
+use crate::replication::oid;
 
-const POSTGRES_EPOCH: i64 = 946684800000; // PostgreSQL epoch in microseconds since UNIX epoch
+/// Microseconds between the UNIX epoch and the Postgres epoch (2000-01-01).
+pub(crate) const POSTGRES_EPOCH: i64 = 946_684_800_000_000;
 
 #[derive(Debug)]
 pub enum ParseError {
@@ -23,6 +26,11 @@ pub enum ParseError {
     ParseIntError(ParseIntError),
     ParseFloatError(ParseFloatError),
     ChronoParseError(chrono::ParseError),
+    InvalidValue(String),
+    /// NULL decoded as a type that is not an `Option`.
+    UnexpectedNull,
+    /// TOAST value that was not replicated because it did not change.
+    UnchangedToast,
 }
 
 impl From<Utf8Error> for ParseError {
@@ -111,8 +119,7 @@ pub struct MessageOrigin {
 
 #[derive(BinRead, Debug)]
 pub struct MessageRelation {
-    pub transaction_id: u16,
-    pub relation_oid: u16,
+    pub relation_oid: u32,
     // namespace_size: u32,
     #[br(map = parse_string)]
     pub namespace: String,
@@ -127,28 +134,23 @@ pub struct MessageRelation {
 
 #[derive(BinRead, Debug)]
 pub struct MessageType {
-    pub transaction_id: Option<u32>,
     pub type_oid: u32,
-    pub namespace_size: u32,
-    #[br(args(namespace_size))]
-    pub namespace: StringWithSize,
-    pub name_size: u32,
-    #[br(args(name_size))]
-    pub name: StringWithSize,
+    #[br(map = parse_string)]
+    pub namespace: String,
+    #[br(map = parse_string)]
+    pub name: String,
 }
 
 #[derive(BinRead, Debug)]
 pub struct MessageInsert {
-    pub transaction_id: u16,
-    pub relation_oid: u16,
+    pub relation_oid: u32,
     #[br(magic = b'N')]
     pub new_tuple: TupleData,
 }
 
 #[derive(BinRead, Debug)]
 pub struct MessageUpdate {
-    pub transaction_id: u16,
-    pub relation_oid: u16,
+    pub relation_oid: u32,
     #[br(try)]
     pub key_or_old_tuple: Option<KeyOrOldTupleData>,
     #[br(magic = b'N')]
@@ -157,15 +159,13 @@ pub struct MessageUpdate {
 
 #[derive(BinRead, Debug)]
 pub struct MessageDelete {
-    pub transaction_id: u16,
-    pub relation_oid: u16,
+    pub relation_oid: u32,
     #[br(try)]
     pub key_or_old_tuple: Option<KeyOrOldTupleData>,
 }
 
 #[derive(BinRead, Debug)]
 pub struct MessageTruncate {
-    pub transaction_id: Option<u32>,
     pub number_of_relations: u32,
     pub option_bits: u8,
     #[br(count = number_of_relations)]
@@ -318,17 +318,9 @@ pub struct TupleData {
 #[derive(BinRead, Debug)]
 pub enum ColumnValue {
     #[br(magic = b'n')]
-    Null {
-        length: u32,
-        #[br(count = length)]
-        data: Vec<u8>,
-    },
+    Null,
     #[br(magic = b'u')]
-    UnchangedToast {
-        length: u32,
-        #[br(count = length)]
-        data: Vec<u8>,
-    },
+    UnchangedToast,
     #[br(magic = b't')]
     Text {
         length: u32,
@@ -343,17 +335,24 @@ pub enum ColumnValue {
     },
 }
 
-
 impl ColumnValue {
-    pub fn as_bytes<'r>(&'r self) -> Result<&'r [u8], ParseError> {
+    pub fn as_bytes(&self) -> Result<&[u8], ParseError> {
         match self {
             ColumnValue::Text { data, .. } => Ok(data),
             ColumnValue::Binary { data, .. } => Ok(data),
-            _ => panic!("Invalid column type"),
+            _ => Err(self.missing_value_error()),
         }
     }
 
-    pub fn as_str<'r>(&'r self) -> Result<&'r str, ParseError> {
+    /// Error of decoding a column without a value as a non-optional type.
+    fn missing_value_error(&self) -> ParseError {
+        match self {
+            ColumnValue::UnchangedToast => ParseError::UnchangedToast,
+            _ => ParseError::UnexpectedNull,
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str, ParseError> {
         Ok(from_utf8(self.as_bytes()?)?)
     }
 }
@@ -367,82 +366,525 @@ pub trait EntityRef {
 }
 
 impl<T: EntityRef> Decode for T {
-    fn decode(value: &ColumnValue) -> Result<Self, ParseError> {
-        Ok(match value {
-            ColumnValue::Text { .. } => {
-                Self::from_entity_id(Decode::decode(value)?)
-            }
-            ColumnValue::Binary { .. } => {
-                Self::from_entity_id(Decode::decode(value)?)
-            }
-            _ => panic!("Invalid column type"),
-        })
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
+        Ok(Self::from_entity_id(Decode::decode(value, type_oid)?))
     }
 }
 
-
+/// Value of a column of the given type, see [`oid`].
+///
+/// pgoutput sends the values in the text format unless its `binary` option is on. The
+/// type OID of the column, from its `Relation` message, selects the binary encoding and
+/// tells apart e.g. a `timestamp` from a `timestamptz`.
 pub trait Decode: Sized {
-    fn decode(value: &ColumnValue) -> Result<Self, ParseError>;
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError>;
 }
 
-impl Decode for Time {
-    fn decode(value: &ColumnValue) -> Result<Self, ParseError> {
+/// NULL is decoded as `None`. An unchanged TOAST value is an error, it has to be resolved
+/// before decoding, see [`TupleRow`](crate::replication::TupleRow).
+impl<T: Decode> Decode for Option<T> {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
+        match value {
+            ColumnValue::Null => Ok(None),
+            _ => Ok(Some(T::decode(value, type_oid)?)),
+        }
+    }
+}
+
+fn unsupported_type<T>(type_oid: u32) -> ParseError {
+    ParseError::InvalidValue(format!(
+        "Cannot decode {} as {}",
+        oid::type_name(type_oid),
+        std::any::type_name::<T>()
+    ))
+}
+
+/// Binary value of a fixed width.
+fn binary_value(data: &[u8], width: usize) -> Result<&[u8], ParseError> {
+    if data.len() != width {
+        return Err(ParseError::InvalidValue(format!(
+            "Expected {} bytes, found {}",
+            width,
+            data.len()
+        )));
+    }
+    Ok(data)
+}
+
+/// Split the first `n` bytes off a binary value.
+fn take<'a>(data: &mut &'a [u8], n: usize) -> Result<&'a [u8], ParseError> {
+    if data.len() < n {
+        return Err(ParseError::InvalidValue(format!(
+            "Expected {} more bytes, found {}",
+            n,
+            data.len()
+        )));
+    }
+    let (head, tail) = data.split_at(n);
+    *data = tail;
+    Ok(head)
+}
+
+fn decode_binary_int<T>(data: &[u8], type_oid: u32) -> Result<i64, ParseError> {
+    Ok(match type_oid {
+        oid::INT2 => BigEndian::read_i16(binary_value(data, 2)?) as i64,
+        oid::INT4 => BigEndian::read_i32(binary_value(data, 4)?) as i64,
+        oid::OID => BigEndian::read_u32(binary_value(data, 4)?) as i64,
+        oid::INT8 => BigEndian::read_i64(binary_value(data, 8)?),
+        _ => return Err(unsupported_type::<T>(type_oid)),
+    })
+}
+
+impl Decode for i64 {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
         Ok(match value {
-            ColumnValue::Text { .. } => {
-                let s = value.as_str()?;
-                let dt = NaiveDateTime::parse_from_str(
-                    s,
-                    if s.contains('+') {
-                        // Contains a time-zone specifier
-                        // This is given for timestamptz for some reason
-                        // Postgres already guarantees this to always be UTC
-                        "%Y-%m-%d %H:%M:%S%.f%#z"
-                    } else {
-                        "%Y-%m-%d %H:%M:%S%.f"
-                    },
-                )?;
-
-                Time::from_naive_datetime(dt)
-            }
-            ColumnValue::Binary { .. } => {
-                let us: i64 = Decode::decode(value)?;
-                Time(POSTGRES_EPOCH + us / 1_000_000)
+            ColumnValue::Text { .. } => value.as_str()?.parse()?,
+            ColumnValue::Binary { data, .. } => decode_binary_int::<Self>(data, type_oid)?,
+            _ => return Err(value.missing_value_error()),
+        })
+    }
+}
+
+impl Decode for i32 {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
+        Ok(match value {
+            ColumnValue::Text { .. } => value.as_str()?.parse()?,
+            ColumnValue::Binary { data, .. } => {
+                let int = decode_binary_int::<Self>(data, type_oid)?;
+                Self::try_from(int).map_err(|_| {
+                    ParseError::InvalidValue(format!("Integer out of range: {}", int))
+                })?
             }
-            _ => panic!("Invalid column type"),
+            _ => return Err(value.missing_value_error()),
         })
     }
 }
 
-impl Decode for i64 {
-    fn decode(value: &ColumnValue) -> Result<Self, ParseError> {
+impl Decode for i16 {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
         Ok(match value {
             ColumnValue::Text { .. } => value.as_str()?.parse()?,
-            ColumnValue::Binary { data, .. } => BigEndian::read_int(&data, data.len()),
-            _ => panic!("Invalid column type"),
+            ColumnValue::Binary { data, .. } => {
+                let int = decode_binary_int::<Self>(data, type_oid)?;
+                Self::try_from(int).map_err(|_| {
+                    ParseError::InvalidValue(format!("Integer out of range: {}", int))
+                })?
+            }
+            _ => return Err(value.missing_value_error()),
+        })
+    }
+}
+
+impl Decode for bool {
+    fn decode(value: &ColumnValue, _type_oid: u32) -> Result<Self, ParseError> {
+        Ok(match value {
+            ColumnValue::Text { .. } => match value.as_str()? {
+                "t" => true,
+                "f" => false,
+                s => return Err(ParseError::InvalidValue(format!("Invalid bool: {}", s))),
+            },
+            ColumnValue::Binary { data, .. } => binary_value(data, 1)?[0] != 0,
+            _ => return Err(value.missing_value_error()),
+        })
+    }
+}
+
+/// Text of a `numeric` in the binary format, which is the number of its base-10000
+/// digits, the weight of the first digit, the sign and the display scale, followed by
+/// the digits.
+fn numeric_to_string(data: &[u8]) -> Result<String, ParseError> {
+    let mut data = data;
+    let ndigits = BigEndian::read_i16(take(&mut data, 2)?).max(0) as usize;
+    let weight = BigEndian::read_i16(take(&mut data, 2)?) as i32;
+    let sign = BigEndian::read_u16(take(&mut data, 2)?);
+    let dscale = BigEndian::read_u16(take(&mut data, 2)?) as usize;
+    let digits: Vec<i16> = binary_value(data, 2 * ndigits)?
+        .chunks(2)
+        .map(BigEndian::read_i16)
+        .collect();
+
+    let mut s = match sign {
+        0x0000 => String::new(),
+        0x4000 => "-".to_string(),
+        0xC000 => return Ok("NaN".to_string()),
+        0xD000 => return Ok("Infinity".to_string()),
+        0xF000 => return Ok("-Infinity".to_string()),
+        _ => {
+            return Err(ParseError::InvalidValue(format!(
+                "Invalid numeric sign: {:#x}",
+                sign
+            )))
+        }
+    };
+    let digit = |k: i32| match usize::try_from(k) {
+        Ok(k) => digits.get(k).copied().unwrap_or(0),
+        Err(_) => 0,
+    };
+    if weight < 0 {
+        s.push('0');
+    } else {
+        s.push_str(&digit(0).to_string());
+        for k in 1..=weight {
+            s.push_str(&format!("{:04}", digit(k)));
+        }
+    }
+    if dscale > 0 {
+        let mut fraction = String::new();
+        let mut k = weight + 1;
+        while fraction.len() < dscale {
+            fraction.push_str(&format!("{:04}", digit(k)));
+            k += 1;
+        }
+        fraction.truncate(dscale);
+        s.push('.');
+        s.push_str(&fraction);
+    }
+    Ok(s)
+}
+
+/// Payload of a `jsonb` in the binary format, which is prefixed by a version.
+fn jsonb_payload(data: &[u8]) -> Result<&[u8], ParseError> {
+    match data.split_first() {
+        Some((1, payload)) => Ok(payload),
+        _ => Err(ParseError::InvalidValue(
+            "Unsupported jsonb version".to_string(),
+        )),
+    }
+}
+
+/// Text of the value, e.g. a `numeric` or a `uuid` in its text representation.
+impl Decode for String {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
+        Ok(match value {
+            ColumnValue::Text { .. } => value.as_str()?.to_string(),
+            ColumnValue::Binary { data, .. } => match type_oid {
+                oid::NUMERIC => numeric_to_string(data)?,
+                oid::UUID => Uuid::decode(value, type_oid)?.to_string(),
+                oid::JSONB => from_utf8(jsonb_payload(data)?)?.to_string(),
+                _ => value.as_str()?.to_string(),
+            },
+            _ => return Err(value.missing_value_error()),
         })
     }
 }
 
 impl Decode for f32 {
-    fn decode(value: &ColumnValue) -> Result<Self, ParseError> {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
         Ok(match value {
             ColumnValue::Text { .. } => value.as_str()?.parse()?,
-            ColumnValue::Binary { data, .. } => BigEndian::read_f32(&data),
-            _ => panic!("Invalid column type"),
+            ColumnValue::Binary { data, .. } if type_oid == oid::FLOAT4 => {
+                BigEndian::read_f32(binary_value(data, 4)?)
+            }
+            ColumnValue::Binary { .. } => f64::decode(value, type_oid)? as f32,
+            _ => return Err(value.missing_value_error()),
         })
     }
 }
 
+/// Also decodes a `numeric`, e.g. a price, rounded to the nearest `f64`.
 impl Decode for f64 {
-    fn decode(value: &ColumnValue) -> Result<Self, ParseError> {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
         Ok(match value {
             ColumnValue::Text { .. } => value.as_str()?.parse()?,
-            ColumnValue::Binary { data, .. } => BigEndian::read_f64(&data),
-            _ => panic!("Invalid column type"),
+            ColumnValue::Binary { data, .. } => match type_oid {
+                oid::FLOAT4 => BigEndian::read_f32(binary_value(data, 4)?) as f64,
+                oid::FLOAT8 => BigEndian::read_f64(binary_value(data, 8)?),
+                oid::NUMERIC => numeric_to_string(data)?.parse()?,
+                oid::INT2 | oid::INT4 | oid::INT8 => {
+                    decode_binary_int::<Self>(data, type_oid)? as f64
+                }
+                _ => return Err(unsupported_type::<Self>(type_oid)),
+            },
+            _ => return Err(value.missing_value_error()),
+        })
+    }
+}
+
+fn postgres_epoch_date() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2000, 1, 1).unwrap()
+}
+
+fn out_of_range_error(what: &str) -> ParseError {
+    ParseError::InvalidValue(format!("{} out of range", what))
+}
+
+/// A `date`, stored in the binary format as days since 2000-01-01.
+impl Decode for NaiveDate {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
+        Ok(match value {
+            ColumnValue::Text { .. } => NaiveDate::parse_from_str(value.as_str()?, "%Y-%m-%d")?,
+            ColumnValue::Binary { data, .. } if type_oid == oid::DATE => {
+                let days = BigEndian::read_i32(binary_value(data, 4)?);
+                postgres_epoch_date()
+                    .checked_add_signed(chrono::Duration::days(days as i64))
+                    .ok_or_else(|| out_of_range_error("Date"))?
+            }
+            ColumnValue::Binary { .. } => return Err(unsupported_type::<Self>(type_oid)),
+            _ => return Err(value.missing_value_error()),
         })
     }
 }
 
+/// A `timestamp`, or a `timestamptz` converted to UTC. Stored in the binary format as
+/// microseconds since 2000-01-01 (in UTC for a `timestamptz`).
+impl Decode for NaiveDateTime {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
+        Ok(match value {
+            ColumnValue::Text { .. } => {
+                let s = value.as_str()?;
+                match type_oid {
+                    // The offset is the one of the `TimeZone` of the replication session.
+                    oid::TIMESTAMPTZ => {
+                        DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f%#z")?.naive_utc()
+                    }
+                    oid::DATE => NaiveDate::decode(value, type_oid)?.into(),
+                    _ => NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")?,
+                }
+            }
+            ColumnValue::Binary { data, .. } => match type_oid {
+                oid::TIMESTAMP | oid::TIMESTAMPTZ => {
+                    let us = BigEndian::read_i64(binary_value(data, 8)?);
+                    NaiveDateTime::from(postgres_epoch_date())
+                        .checked_add_signed(chrono::Duration::microseconds(us))
+                        .ok_or_else(|| out_of_range_error("Timestamp"))?
+                }
+                oid::DATE => NaiveDate::decode(value, type_oid)?.into(),
+                _ => return Err(unsupported_type::<Self>(type_oid)),
+            },
+            _ => return Err(value.missing_value_error()),
+        })
+    }
+}
+
+impl Decode for DateTime<Utc> {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
+        Ok(NaiveDateTime::decode(value, type_oid)?.and_utc())
+    }
+}
+
+impl Decode for Time {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
+        Ok(match value {
+            ColumnValue::Binary { data, .. }
+                if type_oid == oid::TIMESTAMP || type_oid == oid::TIMESTAMPTZ =>
+            {
+                let us = BigEndian::read_i64(binary_value(data, 8)?);
+                POSTGRES_EPOCH
+                    .checked_add(us)
+                    .map(Time)
+                    .ok_or_else(|| out_of_range_error("Timestamp"))?
+            }
+            _ => Time::from_naive_datetime(NaiveDateTime::decode(value, type_oid)?),
+        })
+    }
+}
+
+/// An `interval`, kept in its three parts, as a month and a day do not have a fixed
+/// length.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub months: i32,
+    pub days: i32,
+    pub microseconds: i64,
+}
+
+impl Interval {
+    /// Parse the `postgres` interval style, e.g. `1 year 2 mons -3 days 04:05:06.5`.
+    fn parse(s: &str) -> Result<Self, ParseError> {
+        let invalid = || ParseError::InvalidValue(format!("Invalid interval: {}", s));
+        let mut interval = Interval::default();
+        let mut tokens = s.split_whitespace();
+        while let Some(token) = tokens.next() {
+            if token.contains(':') {
+                let (negative, time) = match token.strip_prefix('-') {
+                    Some(time) => (true, time),
+                    None => (false, token.trim_start_matches('+')),
+                };
+                let mut parts = time.split(':');
+                let hours: i64 = parts.next().ok_or_else(invalid)?.parse()?;
+                let minutes: i64 = parts.next().ok_or_else(invalid)?.parse()?;
+                let seconds = parts.next().unwrap_or("0");
+                if parts.next().is_some() {
+                    return Err(invalid());
+                }
+                let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+                if fraction.len() > 6 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(invalid());
+                }
+                let fraction: i64 = format!("{:0<6}", fraction).parse()?;
+                let seconds: i64 = seconds.parse()?;
+                let microseconds = ((hours * 60 + minutes) * 60 + seconds) * 1_000_000 + fraction;
+                interval.microseconds += if negative {
+                    -microseconds
+                } else {
+                    microseconds
+                };
+            } else {
+                let n: i32 = token.parse()?;
+                match tokens.next().ok_or_else(invalid)?.trim_end_matches('s') {
+                    "year" => interval.months += n * 12,
+                    "mon" => interval.months += n,
+                    "day" => interval.days += n,
+                    _ => return Err(invalid()),
+                }
+            }
+        }
+        Ok(interval)
+    }
+}
+
+impl Decode for Interval {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
+        Ok(match value {
+            ColumnValue::Text { .. } => Interval::parse(value.as_str()?)?,
+            ColumnValue::Binary { data, .. } if type_oid == oid::INTERVAL => {
+                let data = binary_value(data, 16)?;
+                Interval {
+                    microseconds: BigEndian::read_i64(&data[0..8]),
+                    days: BigEndian::read_i32(&data[8..12]),
+                    months: BigEndian::read_i32(&data[12..16]),
+                }
+            }
+            ColumnValue::Binary { .. } => return Err(unsupported_type::<Self>(type_oid)),
+            _ => return Err(value.missing_value_error()),
+        })
+    }
+}
+
+impl Decode for Uuid {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
+        let uuid = match value {
+            ColumnValue::Text { .. } => Uuid::parse_str(value.as_str()?),
+            ColumnValue::Binary { data, .. } if type_oid == oid::UUID => Uuid::from_slice(data),
+            ColumnValue::Binary { .. } => return Err(unsupported_type::<Self>(type_oid)),
+            _ => return Err(value.missing_value_error()),
+        };
+        uuid.map_err(|error| ParseError::InvalidValue(format!("Invalid uuid: {}", error)))
+    }
+}
+
+/// A `json` or a `jsonb`.
+impl Decode for serde_json::Value {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
+        let json = match value {
+            ColumnValue::Binary { data, .. } if type_oid == oid::JSONB => jsonb_payload(data)?,
+            _ => value.as_bytes()?,
+        };
+        serde_json::from_slice(json)
+            .map_err(|error| ParseError::InvalidValue(format!("Invalid json: {}", error)))
+    }
+}
+
+/// Elements of a one-dimensional array in the text format, e.g. `{1,NULL,"a \"b\""}`.
+fn parse_text_array(s: &str) -> Result<Vec<ColumnValue>, ParseError> {
+    let invalid = || ParseError::InvalidValue(format!("Invalid array: {}", s));
+    // Arrays with a lower bound other than 1 are prefixed by their bounds, e.g. `[0:1]=`.
+    let elements = match s.split_once('=') {
+        Some((bounds, elements)) if bounds.starts_with('[') => elements,
+        _ => s,
+    };
+    let elements = elements
+        .strip_prefix('{')
+        .and_then(|elements| elements.strip_suffix('}'))
+        .ok_or_else(invalid)?;
+
+    let mut values = Vec::new();
+    if elements.is_empty() {
+        return Ok(values);
+    }
+    let mut chars = elements.chars().peekable();
+    loop {
+        let mut element = String::new();
+        let quoted = chars.peek() == Some(&'"');
+        if quoted {
+            chars.next();
+            loop {
+                match chars.next().ok_or_else(invalid)? {
+                    '"' => break,
+                    '\\' => element.push(chars.next().ok_or_else(invalid)?),
+                    c => element.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                match c {
+                    '{' => {
+                        return Err(ParseError::InvalidValue(
+                            "Only one-dimensional arrays are supported".to_string(),
+                        ))
+                    }
+                    '\\' => element.push(chars.next().ok_or_else(invalid)?),
+                    c => element.push(c),
+                }
+            }
+        }
+        values.push(if !quoted && element.eq_ignore_ascii_case("NULL") {
+            ColumnValue::Null
+        } else {
+            ColumnValue::Text {
+                length: element.len() as u32,
+                data: element.into_bytes(),
+            }
+        });
+        match chars.next() {
+            Some(',') => continue,
+            None => return Ok(values),
+            Some(_) => return Err(invalid()),
+        }
+    }
+}
+
+/// Element type and elements of a one-dimensional array in the binary format.
+fn parse_binary_array(data: &[u8]) -> Result<(u32, Vec<ColumnValue>), ParseError> {
+    let mut data = data;
+    let dimensions = BigEndian::read_i32(take(&mut data, 4)?);
+    let _has_nulls = take(&mut data, 4)?;
+    let element_type = BigEndian::read_u32(take(&mut data, 4)?);
+    match dimensions {
+        0 => return Ok((element_type, Vec::new())),
+        1 => {}
+        _ => {
+            return Err(ParseError::InvalidValue(
+                "Only one-dimensional arrays are supported".to_string(),
+            ))
+        }
+    }
+    let length = BigEndian::read_i32(take(&mut data, 4)?);
+    let _lower_bound = take(&mut data, 4)?;
+
+    let mut values = Vec::with_capacity(length.max(0) as usize);
+    for _ in 0..length {
+        let element_length = BigEndian::read_i32(take(&mut data, 4)?);
+        values.push(match u32::try_from(element_length) {
+            Ok(length) => ColumnValue::Binary {
+                length,
+                data: take(&mut data, length as usize)?.to_vec(),
+            },
+            Err(_) => ColumnValue::Null,
+        });
+    }
+    Ok((element_type, values))
+}
+
+/// A one-dimensional array, with `Vec<Option<T>>` for arrays containing NULL. The
+/// elements of an array type that is not built in are decoded as `text`.
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(value: &ColumnValue, type_oid: u32) -> Result<Self, ParseError> {
+        let (element_type, values) = match value {
+            ColumnValue::Text { .. } => (
+                oid::array_element_type(type_oid).unwrap_or(oid::TEXT),
+                parse_text_array(value.as_str()?)?,
+            ),
+            ColumnValue::Binary { data, .. } => parse_binary_array(data)?,
+            _ => return Err(value.missing_value_error()),
+        };
+        values
+            .iter()
+            .map(|value| T::decode(value, element_type))
+            .collect()
+    }
+}
+
 #[derive(BinRead, Debug)]
 #[br(big)]
 pub enum KeyOrOldTupleData {
@@ -456,3 +898,251 @@ pub fn decode(msg: &[u8]) -> Result<LogicalReplicationMessage, binrw::Error> {
     LogicalReplicationMessage::read(&mut binrw::io::Cursor::new(msg))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> ColumnValue {
+        ColumnValue::Text {
+            length: value.len() as u32,
+            data: value.as_bytes().to_vec(),
+        }
+    }
+
+    fn binary(data: &[u8]) -> ColumnValue {
+        ColumnValue::Binary {
+            length: data.len() as u32,
+            data: data.to_vec(),
+        }
+    }
+
+    fn numeric(weight: i16, sign: u16, dscale: u16, digits: &[i16]) -> ColumnValue {
+        let mut data = Vec::new();
+        for part in [digits.len() as i16, weight, sign as i16, dscale as i16] {
+            data.extend(part.to_be_bytes());
+        }
+        for digit in digits {
+            data.extend(digit.to_be_bytes());
+        }
+        binary(&data)
+    }
+
+    #[test]
+    fn integers_are_decoded_by_their_width() {
+        assert_eq!(
+            i64::decode(&binary(&7_i16.to_be_bytes()), oid::INT2).unwrap(),
+            7
+        );
+        assert_eq!(
+            i64::decode(&binary(&(-7_i32).to_be_bytes()), oid::INT4).unwrap(),
+            -7
+        );
+        assert_eq!(
+            i64::decode(&binary(&i64::MIN.to_be_bytes()), oid::INT8).unwrap(),
+            i64::MIN
+        );
+        assert_eq!(
+            i32::decode(&binary(&5_i32.to_be_bytes()), oid::INT4).unwrap(),
+            5
+        );
+        assert_eq!(i16::decode(&text("-12"), oid::INT2).unwrap(), -12);
+        assert!(i64::decode(&binary(&7_i16.to_be_bytes()), oid::INT8).is_err());
+        assert!(i16::decode(&binary(&70_000_i32.to_be_bytes()), oid::INT4).is_err());
+        assert!(bool::decode(&binary(&[1]), oid::BOOL).unwrap());
+        assert!(!bool::decode(&text("f"), oid::BOOL).unwrap());
+    }
+
+    #[test]
+    fn numerics_are_decoded() {
+        let price = numeric(1, 0x0000, 3, &[1, 2345, 6780]);
+        assert_eq!(String::decode(&price, oid::NUMERIC).unwrap(), "12345.678");
+        assert_eq!(f64::decode(&price, oid::NUMERIC).unwrap(), 12345.678);
+
+        let small = numeric(-1, 0x4000, 6, &[12]);
+        assert_eq!(String::decode(&small, oid::NUMERIC).unwrap(), "-0.001200");
+        let large = numeric(2, 0x0000, 0, &[5]);
+        assert_eq!(String::decode(&large, oid::NUMERIC).unwrap(), "500000000");
+        let nan = numeric(0, 0xC000, 0, &[]);
+        assert!(f64::decode(&nan, oid::NUMERIC).unwrap().is_nan());
+
+        assert_eq!(f64::decode(&text("-12.50"), oid::NUMERIC).unwrap(), -12.5);
+        assert_eq!(f32::decode(&price, oid::NUMERIC).unwrap(), 12345.678);
+    }
+
+    #[test]
+    fn timestamps_are_decoded_by_their_type() {
+        let expected = NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_milli_opt(11, 30, 0, 250)
+            .unwrap();
+        let timestamp = text("2024-03-01 11:30:00.25");
+        assert_eq!(
+            NaiveDateTime::decode(&timestamp, oid::TIMESTAMP).unwrap(),
+            expected
+        );
+        let timestamptz = text("2024-03-01 12:30:00.25+01");
+        assert_eq!(
+            NaiveDateTime::decode(&timestamptz, oid::TIMESTAMPTZ).unwrap(),
+            expected
+        );
+        let timestamptz = text("2024-03-01 06:00:00.25-05:30");
+        assert_eq!(
+            DateTime::<Utc>::decode(&timestamptz, oid::TIMESTAMPTZ).unwrap(),
+            expected.and_utc()
+        );
+
+        let us = (expected - NaiveDateTime::from(postgres_epoch_date()))
+            .num_microseconds()
+            .unwrap();
+        assert_eq!(
+            NaiveDateTime::decode(&binary(&us.to_be_bytes()), oid::TIMESTAMP).unwrap(),
+            expected
+        );
+
+        let date = NaiveDate::from_ymd_opt(1999, 12, 31).unwrap();
+        assert_eq!(
+            NaiveDate::decode(&text("1999-12-31"), oid::DATE).unwrap(),
+            date
+        );
+        assert_eq!(
+            NaiveDate::decode(&binary(&(-1_i32).to_be_bytes()), oid::DATE).unwrap(),
+            date
+        );
+    }
+
+    #[test]
+    fn timestamptz_round_trip() {
+        let times = [
+            "2024-03-01T12:30:00.123456+01:00",
+            "2000-01-01T00:00:00+00:00",
+            "1999-12-31T23:59:59.999999+00:00",
+            "1969-07-20T20:17:40.5+00:00",
+            "1900-01-01T00:00:00.000001-05:00",
+        ];
+        for s in times {
+            let time = Time::from_string(s).unwrap();
+            let datetime = DateTime::parse_from_rfc3339(s).unwrap();
+
+            let text_value = text(&datetime.format("%Y-%m-%d %H:%M:%S%.f%:z").to_string());
+            assert_eq!(
+                Time::decode(&text_value, oid::TIMESTAMPTZ).unwrap(),
+                time,
+                "{}",
+                s
+            );
+
+            let us = time.timestamp_micros() - POSTGRES_EPOCH;
+            let binary_value = binary(&us.to_be_bytes());
+            assert_eq!(
+                Time::decode(&binary_value, oid::TIMESTAMPTZ).unwrap(),
+                time,
+                "{}",
+                s
+            );
+            assert_eq!(Time::from_string(&time.to_string()).unwrap(), time);
+        }
+
+        let pre_epoch = Time::from_string("1999-12-31T23:59:59.5+00:00").unwrap();
+        assert_eq!(pre_epoch.timestamp_micros(), POSTGRES_EPOCH - 500_000);
+        assert_eq!(pre_epoch.timestamp(), 946_684_799);
+        let pre_unix_epoch = Time::from_string("1969-12-31T23:59:59.5+00:00").unwrap();
+        assert_eq!(pre_unix_epoch.timestamp_micros(), -500_000);
+        assert_eq!(pre_unix_epoch.timestamp(), -1);
+        assert!(Time::decode(&binary(&i64::MAX.to_be_bytes()), oid::TIMESTAMPTZ).is_err());
+    }
+
+    #[test]
+    fn intervals_are_decoded() {
+        let interval = Interval {
+            months: 14,
+            days: -3,
+            microseconds: -(((4 * 60 + 5) * 60 + 6) * 1_000_000 + 500_000),
+        };
+        assert_eq!(
+            Interval::decode(&text("1 year 2 mons -3 days -04:05:06.5"), oid::INTERVAL).unwrap(),
+            interval
+        );
+        assert_eq!(
+            Interval::decode(&text("1 day"), oid::INTERVAL).unwrap(),
+            Interval {
+                days: 1,
+                ..Interval::default()
+            }
+        );
+
+        let mut data = interval.microseconds.to_be_bytes().to_vec();
+        data.extend(interval.days.to_be_bytes());
+        data.extend(interval.months.to_be_bytes());
+        assert_eq!(
+            Interval::decode(&binary(&data), oid::INTERVAL).unwrap(),
+            interval
+        );
+        assert!(Interval::decode(&text("1 fortnight"), oid::INTERVAL).is_err());
+    }
+
+    #[test]
+    fn uuids_and_json_are_decoded() {
+        let uuid = Uuid::parse_str("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8").unwrap();
+        assert_eq!(
+            Uuid::decode(&text(&uuid.to_string()), oid::UUID).unwrap(),
+            uuid
+        );
+        assert_eq!(
+            Uuid::decode(&binary(uuid.as_bytes()), oid::UUID).unwrap(),
+            uuid
+        );
+        assert_eq!(
+            String::decode(&binary(uuid.as_bytes()), oid::UUID).unwrap(),
+            uuid.to_string()
+        );
+
+        let json = serde_json::json!({"price": 12.5});
+        assert_eq!(
+            serde_json::Value::decode(&text(r#"{"price": 12.5}"#), oid::JSONB).unwrap(),
+            json
+        );
+        let mut jsonb = vec![1];
+        jsonb.extend(br#"{"price": 12.5}"#);
+        assert_eq!(
+            serde_json::Value::decode(&binary(&jsonb), oid::JSONB).unwrap(),
+            json
+        );
+    }
+
+    #[test]
+    fn arrays_are_decoded() {
+        assert_eq!(
+            Vec::<i64>::decode(&text("{1,-2,3}"), oid::INT8_ARRAY).unwrap(),
+            vec![1, -2, 3]
+        );
+        assert_eq!(
+            Vec::<Option<String>>::decode(
+                &text(r#"{plain,"with space","quote \" and \\",NULL,"NULL"}"#),
+                oid::TEXT_ARRAY
+            )
+            .unwrap(),
+            vec![
+                Some("plain".to_string()),
+                Some("with space".to_string()),
+                Some(r#"quote " and \"#.to_string()),
+                None,
+                Some("NULL".to_string()),
+            ]
+        );
+        assert_eq!(
+            Vec::<f64>::decode(&text("{}"), oid::FLOAT8_ARRAY).unwrap(),
+            Vec::<f64>::new()
+        );
+        assert!(Vec::<i64>::decode(&text("{{1,2},{3,4}}"), oid::INT8_ARRAY).is_err());
+        assert!(Vec::<i64>::decode(&text("{1,NULL}"), oid::INT8_ARRAY).is_err());
+
+        let mut data = Vec::new();
+        for part in [1, 1, oid::INT4 as i32, 2, 1, 4, 7, -1] {
+            data.extend(part.to_be_bytes());
+        }
+        assert_eq!(
+            Vec::<Option<i32>>::decode(&binary(&data), oid::INT4_ARRAY).unwrap(),
+            vec![Some(7), None]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use crate::core::Error;
+use crate::replication::oid;
+use crate::replication::pgoutput::{MessageRelation, MessageType};
+
+/// Column of a replicated relation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelationColumn {
+    pub name: String,
+    pub type_oid: u32,
+    /// Name of the type, for the error messages.
+    pub type_name: String,
+}
+
+/// Replicated relation, as described by its last `Relation` message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relation {
+    pub oid: u32,
+    pub namespace: String,
+    pub name: String,
+    pub columns: Vec<RelationColumn>,
+}
+
+impl Relation {
+    /// Position of the column in the tuples of the relation.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name == name)
+    }
+}
+
+impl From<&MessageRelation> for Relation {
+    fn from(message: &MessageRelation) -> Self {
+        Relation {
+            oid: message.relation_oid,
+            namespace: message.namespace.clone(),
+            name: message.relation_name.clone(),
+            columns: message
+                .columns
+                .iter()
+                .map(|column| RelationColumn {
+                    name: column.name.clone(),
+                    type_oid: column.type_oid,
+                    type_name: oid::type_name(column.type_oid),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Relations seen in the replicated changes, keyed by OID.
+///
+/// pgoutput describes a relation only before its first change in a session and after its
+/// definition changes, so the relations are kept across transactions. The same holds for
+/// the types that are not built in, which are described by `Type` messages before the
+/// relations using them.
+#[derive(Debug, Default)]
+pub struct RelationCache {
+    relations: HashMap<u32, Relation>,
+    type_names: HashMap<u32, String>,
+}
+
+impl RelationCache {
+    pub fn update(&mut self, message: &MessageRelation) {
+        let mut relation = Relation::from(message);
+        for column in relation.columns.iter_mut() {
+            if let Some(type_name) = self.type_names.get(&column.type_oid) {
+                column.type_name = type_name.clone();
+            }
+        }
+        self.relations.insert(message.relation_oid, relation);
+    }
+
+    pub fn update_type(&mut self, message: &MessageType) {
+        self.type_names.insert(
+            message.type_oid,
+            format!("{}.{}", message.namespace, message.name),
+        );
+    }
+
+    pub fn get(&self, relation_oid: u32) -> Result<&Relation, Error> {
+        self.relations.get(&relation_oid).ok_or_else(|| {
+            Error::ReplicationError(format!("Relation {} was not described", relation_oid))
+        })
+    }
+}
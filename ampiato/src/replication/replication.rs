@@ -2,13 +2,224 @@ use crate::core::Error;
 use sqlx::{PgConnection, PgPool};
 
 use crate::replication::{
+    from_tuple_data::TableFromTupleData,
     pgoutput::{self, LogicalReplicationMessage},
     print::RowData,
 };
 
+/// Replication slot the changes are consumed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplicationSlot {
+    /// Slot with a random name, dropped by Postgres when the connection is closed. The
+    /// changes committed while the process is down are lost.
+    Temporary,
+    /// Named slot kept by Postgres across restarts. It is created on the first start and
+    /// reused afterwards, so that the changes committed while the process is down are
+    /// replicated on the next start.
+    Persistent(String),
+}
+
+/// Slot and publication the changes are replicated from, e.g.
+/// `ReplicationConfig::new("pricing").tables::<Table>()`.
+///
+/// The default is a temporary slot and a publication named `ampiato` for all tables.
+///
+/// With a temporary slot, [`Replication`] creates a publication of its own, named after
+/// the configured one with the random suffix of the slot, and drops it when the
+/// replication stops. The publications left behind by a process that stopped without
+/// [`Replication::close_and_cleanup`], i.e. named `{publication}_{digits}` without a
+/// temporary slot `ampiato_slot_{digits}`, are dropped by the next one. Otherwise the
+/// publication is created if it does not exist, but it is never dropped, as a persistent
+/// slot decodes the pending changes with it. A publication that exists with other tables
+/// is refused rather than changed, as others may depend on it. An existing publication
+/// is only checked to exist.
+///
+/// The replica identity of the tables (by default their primary key) has to contain the
+/// selector and time columns, so that deleted rows can be found. With
+/// `REPLICA IDENTITY FULL`, unchanged TOAST values of updated rows are replicated too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicationConfig {
+    pub slot: ReplicationSlot,
+    /// Name of the publication the changes are decoded with.
+    pub publication: String,
+    /// Tables of the publication, `None` for all tables.
+    pub tables: Option<Vec<String>>,
+    /// Use an existing publication as it is, instead of creating it (and dropping it
+    /// when the replication stops). Needs no privileges beyond the replication itself.
+    pub existing_publication: bool,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        ReplicationConfig::new("ampiato")
+    }
+}
+
+impl ReplicationConfig {
+    pub fn new(publication: impl Into<String>) -> Self {
+        ReplicationConfig {
+            slot: ReplicationSlot::Temporary,
+            publication: publication.into(),
+            tables: None,
+            existing_publication: false,
+        }
+    }
+
+    pub fn slot(mut self, slot: ReplicationSlot) -> Self {
+        self.slot = slot;
+        self
+    }
+
+    /// Restrict the publication to the tables of the generated `Table` enum.
+    pub fn tables<T: TableFromTupleData>(mut self) -> Self {
+        self.tables = Some(T::table_names().into_iter().map(String::from).collect());
+        self
+    }
+
+    /// Attach to the publication as it is; it must already exist.
+    pub fn existing_publication(mut self) -> Self {
+        self.existing_publication = true;
+        self
+    }
+
+    /// Rows of `(puballtables, tablename)` of the publication, parsed by
+    /// [`ExistingPublication::from_rows`].
+    pub(crate) fn existing_tables_sql(&self) -> String {
+        format!(
+            "SELECT p.puballtables, t.tablename::TEXT FROM pg_publication p \
+             LEFT JOIN pg_publication_tables t ON t.pubname = p.pubname \
+             WHERE p.pubname = {};",
+            quote_literal(&self.publication)
+        )
+    }
+
+    pub(crate) fn create_publication_sql(&self) -> String {
+        format!(
+            "CREATE PUBLICATION {} {};",
+            quote_identifier(&self.publication),
+            self.publication_tables_sql()
+        )
+    }
+
+    pub(crate) fn drop_publication_sql(&self) -> String {
+        format!(
+            "DROP PUBLICATION IF EXISTS {};",
+            quote_identifier(&self.publication)
+        )
+    }
+
+    fn publication_tables_sql(&self) -> String {
+        match &self.tables {
+            None => "FOR ALL TABLES".to_string(),
+            Some(tables) => format!("FOR TABLE {}", quote_tables(tables)),
+        }
+    }
+
+    /// Value of the `publication_names` option of pgoutput.
+    pub(crate) fn publication_names(&self) -> String {
+        quote_identifier(&self.publication)
+    }
+
+    /// Same config with the publication renamed to `{publication}_{id}`, so that the
+    /// publication created for a temporary slot can be dropped without touching a
+    /// publication of the same name used by others.
+    pub(crate) fn with_publication_suffix(mut self, id: u32) -> Self {
+        self.publication = format!("{}_{}", self.publication, id);
+        self
+    }
+
+    /// Names of the publications created by [`ReplicationConfig::with_publication_suffix`]
+    /// whose temporary slot no longer exists, e.g. after a crash.
+    pub(crate) fn stale_publications_sql(&self) -> String {
+        let prefix = quote_literal(&format!("{}_", self.publication));
+        let suffix = format!("substr(pubname, length({}) + 1)", prefix);
+        format!(
+            "SELECT pubname::TEXT FROM pg_publication \
+             WHERE left(pubname, length({prefix})) = {prefix} AND {suffix} ~ '^[0-9]+$' \
+             AND NOT EXISTS (SELECT 1 FROM pg_replication_slots \
+             WHERE slot_name = '{TEMPORARY_SLOT_PREFIX}' || {suffix});"
+        )
+    }
+
+    /// Statements preparing the publication before the slot is created, given the one
+    /// that exists already. Fails if an existing publication is required but missing, or
+    /// if the publication exists with other tables.
+    ///
+    /// A publication is never changed or dropped here, as it may not have been created by
+    /// us.
+    pub(crate) fn prepare_publication_sql(
+        &self,
+        existing: &ExistingPublication,
+    ) -> Result<Vec<String>, Error> {
+        if self.tables.as_ref().is_some_and(|tables| tables.is_empty()) {
+            return Err(Error::ReplicationError(format!(
+                "Publication {} has no tables",
+                self.publication
+            )));
+        }
+        let has_tables = match (&self.tables, existing) {
+            (_, ExistingPublication::Missing) => {
+                return if self.existing_publication {
+                    Err(Error::ReplicationError(format!(
+                        "Publication {} does not exist",
+                        self.publication
+                    )))
+                } else {
+                    Ok(vec![self.create_publication_sql()])
+                };
+            }
+            _ if self.existing_publication => true,
+            (None, ExistingPublication::AllTables) => true,
+            (Some(tables), ExistingPublication::Tables(existing_tables)) => {
+                let mut tables = tables.clone();
+                let mut existing_tables = existing_tables.clone();
+                tables.sort();
+                existing_tables.sort();
+                tables == existing_tables
+            }
+            _ => false,
+        };
+        if !has_tables {
+            return Err(Error::ReplicationError(format!(
+                "Publication {} already exists with other tables",
+                self.publication
+            )));
+        }
+        Ok(Vec::new())
+    }
+}
+
+/// Publication found in the database, see [`ReplicationConfig::existing_tables_sql`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ExistingPublication {
+    Missing,
+    AllTables,
+    Tables(Vec<String>),
+}
+
+impl ExistingPublication {
+    pub(crate) fn from_rows(rows: impl IntoIterator<Item = (bool, Option<String>)>) -> Self {
+        let mut rows = rows.into_iter().peekable();
+        match rows.peek() {
+            None => ExistingPublication::Missing,
+            Some((true, _)) => ExistingPublication::AllTables,
+            Some((false, _)) => {
+                ExistingPublication::Tables(rows.filter_map(|(_, table)| table).collect())
+            }
+        }
+    }
+}
+
+/// Prefix of the name of a temporary slot, followed by a random number.
+pub(crate) const TEMPORARY_SLOT_PREFIX: &str = "ampiato_slot_";
+
 pub struct Replication {
     db_connection: PgConnection,
     replication_slot_name: String,
+    is_temporary: bool,
+    config: ReplicationConfig,
+    /// Position up to which the changes were confirmed as applied.
+    confirmed_lsn: Option<u64>,
     is_closed: bool,
 }
 
@@ -21,42 +232,117 @@ impl Replication {
         self.is_closed = true;
     }
 
+    /// Close the replication. The publication is dropped only if it was created for a
+    /// temporary slot; a persistent slot keeps the publication it decodes the changes
+    /// with.
     pub async fn close_and_cleanup(&mut self) -> Result<(), Error> {
         if self.is_closed {
             return Ok(());
         }
 
-        sqlx::query(r#"DROP PUBLICATION IF EXISTS ampiato;"#)
-            .execute(&mut self.db_connection)
-            .await?;
+        if self.is_temporary && !self.config.existing_publication {
+            sqlx::query(&self.config.drop_publication_sql())
+                .execute(&mut self.db_connection)
+                .await?;
+        }
 
         self.close();
 
         Ok(())
     }
 
-    pub async fn grab_changes(&mut self) -> Result<Vec<LogicalReplicationMessage>, Error> {
-        let res = sqlx::query_as!(
-            RowData,
+    /// Fetch the changes that were not confirmed yet by [`Replication::confirm`].
+    ///
+    /// The changes stay in the slot, so the same changes are returned again until they
+    /// are confirmed. The transactions committed up to `after_lsn` (their end LSN) are
+    /// filtered out on the server, e.g. the ones already applied, except for the relations
+    /// and types they describe, which are returned outside of any transaction.
+    ///
+    /// The server still decodes all the changes since the confirmed position on every
+    /// call. Confirm them regularly, or use a [`ReplicationStream`], which reads them only
+    /// once.
+    ///
+    /// [`ReplicationStream`]: super::ReplicationStream
+    pub async fn grab_changes(
+        &mut self,
+        after_lsn: Option<u64>,
+    ) -> Result<Vec<LogicalReplicationMessage>, Error> {
+        // The end LSN of a transaction is the LSN of its commit message ('C'); relation
+        // ('R') and type ('Y') messages are kept for the later transactions.
+        let res = sqlx::query_as::<_, RowData>(
             r#"
+                WITH changes AS MATERIALIZED (
+                    SELECT lsn, xid, data, n
+                    FROM pg_logical_slot_peek_binary_changes(
+                        $1, NULL, NULL, 'proto_version', '1', 'publication_names', $2
+                    ) WITH ORDINALITY AS c(lsn, xid, data, n)
+                )
                 SELECT
-                    lsn::TEXT as "lsn!",
-                    xid::TEXT as "xid!",
+                    lsn::TEXT as "lsn",
+                    xid::TEXT as "xid",
                     data
                 FROM
-                    pg_logical_slot_get_binary_changes($1, NULL, NULL, 'proto_version', '1', 'publication_names', 'ampiato');
+                    changes
+                WHERE
+                    $3::pg_lsn IS NULL
+                    OR get_byte(data, 0) IN (82, 89)
+                    OR xid IN (
+                        SELECT xid FROM changes
+                        WHERE get_byte(data, 0) = 67 AND lsn > $3::pg_lsn
+                    )
+                ORDER BY n;
             "#,
-            self.replication_slot_name
-        ).fetch_all(&mut self.db_connection).await.unwrap();
+        )
+        .bind(&self.replication_slot_name)
+        .bind(self.config.publication_names())
+        .bind(after_lsn.map(format_lsn))
+        .fetch_all(&mut self.db_connection)
+        .await?;
         let changes = res
             .iter()
-            .map(|row| pgoutput::decode(&row.data.as_deref().unwrap()).unwrap())
-            .collect::<Vec<_>>();
+            .map(|row| {
+                let data = row.data.as_deref().ok_or_else(|| {
+                    Error::ReplicationError(format!("Change at {} has no data", row.lsn))
+                })?;
+                pgoutput::decode(data).map_err(|error| {
+                    Error::ReplicationError(format!("Invalid change at {}: {}", row.lsn, error))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         Ok(changes)
     }
+
+    /// Confirm that the changes up to `lsn` (the end LSN of the last applied transaction)
+    /// were applied, so that the slot can release them.
+    pub async fn confirm(&mut self, lsn: u64) -> Result<(), Error> {
+        if self.confirmed_lsn.is_some_and(|confirmed_lsn| lsn <= confirmed_lsn) {
+            return Ok(());
+        }
+
+        sqlx::query(r#"SELECT pg_replication_slot_advance($1, $2::pg_lsn);"#)
+            .bind(&self.replication_slot_name)
+            .bind(format_lsn(lsn))
+            .execute(&mut self.db_connection)
+            .await?;
+        self.confirmed_lsn = Some(lsn);
+
+        Ok(())
+    }
+
+    /// Position up to which the changes were confirmed as applied.
+    pub fn confirmed_lsn(&self) -> Option<u64> {
+        self.confirmed_lsn
+    }
+
+    /// Whether the slot is dropped together with the connection.
+    pub fn is_temporary(&self) -> bool {
+        self.is_temporary
+    }
 }
 
+/// The publication of a temporary slot cannot be dropped without a query, so it is left
+/// to the next [`Replication`] with the same config.
 impl Drop for Replication {
     fn drop(&mut self) {
         self.close();
@@ -65,29 +351,206 @@ impl Drop for Replication {
 
 impl Replication {
     pub async fn from_pool(pool: &PgPool) -> Result<Self, sqlx::Error> {
-        let mut db_connection = pool.acquire().await?.detach();
+        Self::from_pool_with_config(pool, ReplicationConfig::default()).await
+    }
 
-        let replication_slot_name = format!("ampiato_slot_{}", rand::random::<u32>());
+    pub async fn from_pool_with_config(
+        pool: &PgPool,
+        config: ReplicationConfig,
+    ) -> Result<Self, sqlx::Error> {
+        let mut db_connection = pool.acquire().await?.detach();
 
-        sqlx::query(r#"DROP PUBLICATION IF EXISTS ampiato;"#)
-            .execute(&mut db_connection)
+        let is_temporary = config.slot == ReplicationSlot::Temporary;
+        let owns_publication = is_temporary && !config.existing_publication;
+        let id = rand::random::<u32>();
+        let lock_key = format!("ampiato_publication_{}", config.publication);
+        let config = if owns_publication {
+            // Other processes neither drop the publication before its slot is created, nor
+            // create theirs while the stale ones are dropped.
+            sqlx::query("SELECT pg_advisory_lock(hashtext($1));")
+                .bind(&lock_key)
+                .execute(&mut db_connection)
+                .await?;
+            let stale_publications: Vec<String> =
+                sqlx::query_scalar(&config.stale_publications_sql())
+                    .fetch_all(&mut db_connection)
+                    .await?;
+            for publication in stale_publications {
+                let stale = ReplicationConfig::new(publication);
+                sqlx::query(&stale.drop_publication_sql())
+                    .execute(&mut db_connection)
+                    .await?;
+            }
+            config.with_publication_suffix(id)
+        } else {
+            config
+        };
+        let rows: Vec<(bool, Option<String>)> = sqlx::query_as(&config.existing_tables_sql())
+            .fetch_all(&mut db_connection)
             .await?;
+        let statements = config
+            .prepare_publication_sql(&ExistingPublication::from_rows(rows))
+            .map_err(|error| sqlx::Error::Configuration(error.into()))?;
+        for sql in statements {
+            sqlx::query(&sql).execute(&mut db_connection).await?;
+        }
 
-        sqlx::query!(
-            r#"SELECT pg_create_logical_replication_slot($1, 'pgoutput', temporary := true);"#,
-            replication_slot_name
-        )
-        .fetch_one(&mut db_connection)
-        .await?;
+        let (replication_slot_name, confirmed_lsn) = match &config.slot {
+            ReplicationSlot::Temporary => {
+                let replication_slot_name = format!("{}{}", TEMPORARY_SLOT_PREFIX, id);
 
-        sqlx::query(r#"CREATE PUBLICATION ampiato FOR ALL TABLES;"#)
-            .execute(&mut db_connection)
-            .await?;
+                sqlx::query!(
+                    r#"SELECT pg_create_logical_replication_slot($1, 'pgoutput', temporary := true);"#,
+                    replication_slot_name
+                )
+                .fetch_one(&mut db_connection)
+                .await?;
+
+                if owns_publication {
+                    sqlx::query("SELECT pg_advisory_unlock(hashtext($1));")
+                        .bind(&lock_key)
+                        .execute(&mut db_connection)
+                        .await?;
+                }
+
+                (replication_slot_name, None)
+            }
+            ReplicationSlot::Persistent(replication_slot_name) => {
+                let confirmed_lsn: Option<Option<String>> = sqlx::query_scalar(
+                    r#"SELECT confirmed_flush_lsn::TEXT FROM pg_replication_slots WHERE slot_name = $1;"#,
+                )
+                .bind(replication_slot_name)
+                .fetch_optional(&mut db_connection)
+                .await?;
+                let confirmed_lsn = match confirmed_lsn {
+                    Some(confirmed_lsn) => confirmed_lsn.as_deref().and_then(parse_lsn),
+                    None => {
+                        sqlx::query(
+                            r#"SELECT pg_create_logical_replication_slot($1, 'pgoutput', temporary := false);"#,
+                        )
+                        .bind(replication_slot_name)
+                        .execute(&mut db_connection)
+                        .await?;
+                        None
+                    }
+                };
+
+                (replication_slot_name.clone(), confirmed_lsn)
+            }
+        };
 
         Ok(Replication {
             db_connection,
             replication_slot_name,
+            is_temporary,
+            config,
+            confirmed_lsn,
             is_closed: false,
         })
     }
 }
+
+/// Format an LSN in the `XXX/XXX` notation of Postgres.
+pub fn format_lsn(lsn: u64) -> String {
+    format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF)
+}
+
+/// Parse an LSN in the `XXX/XXX` notation of Postgres.
+pub fn parse_lsn(lsn: &str) -> Option<u64> {
+    let (high, low) = lsn.split_once('/')?;
+    let high = u64::from_str_radix(high, 16).ok()?;
+    let low = u64::from_str_radix(low, 16).ok()?;
+    Some((high << 32) | low)
+}
+
+pub(crate) fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+pub(crate) fn quote_literal(literal: &str) -> String {
+    format!("'{}'", literal.replace('\'', "''"))
+}
+
+fn quote_tables(tables: &[String]) -> String {
+    let tables: Vec<String> = tables.iter().map(|t| quote_identifier(t)).collect();
+    tables.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lsn_round_trip() {
+        assert_eq!(format_lsn(0x1_0000_00A0), "1/A0");
+        assert_eq!(parse_lsn("16/B374D848"), Some(0x16_B374_D848));
+        assert_eq!(parse_lsn(&format_lsn(u64::MAX)), Some(u64::MAX));
+        assert_eq!(parse_lsn("invalid"), None);
+    }
+
+    #[test]
+    fn publication_sql() {
+        let mut config = ReplicationConfig::new("pricing");
+        assert_eq!(
+            config.create_publication_sql(),
+            r#"CREATE PUBLICATION "pricing" FOR ALL TABLES;"#
+        );
+
+        config.tables = Some(vec!["BlokVykon".to_string(), "Market".to_string()]);
+        assert_eq!(
+            config.create_publication_sql(),
+            r#"CREATE PUBLICATION "pricing" FOR TABLE "BlokVykon", "Market";"#
+        );
+        assert!(ReplicationConfig::new("it's")
+            .existing_tables_sql()
+            .ends_with("WHERE p.pubname = 'it''s';"));
+        assert!(ReplicationConfig::new("it's")
+            .stale_publications_sql()
+            .contains("left(pubname, length('it''s_')) = 'it''s_'"));
+    }
+
+    #[test]
+    fn publication_is_prepared_for_the_slot() {
+        use ExistingPublication::*;
+
+        let market = || vec!["Market".to_string()];
+        let config = ReplicationConfig::new("pricing");
+        assert_eq!(
+            config.prepare_publication_sql(&Missing).unwrap(),
+            vec![config.create_publication_sql()]
+        );
+        // An existing publication is never changed, nor dropped.
+        assert!(config.prepare_publication_sql(&AllTables).unwrap().is_empty());
+        assert!(config.prepare_publication_sql(&Tables(market())).is_err());
+        assert_eq!(
+            config.clone().with_publication_suffix(7).create_publication_sql(),
+            r#"CREATE PUBLICATION "pricing_7" FOR ALL TABLES;"#
+        );
+
+        let mut config = config;
+        config.tables = Some(vec!["Market".to_string(), "BlokVykon".to_string()]);
+        let tables = Tables(vec!["BlokVykon".to_string(), "Market".to_string()]);
+        assert!(config.prepare_publication_sql(&tables).unwrap().is_empty());
+        assert!(config.prepare_publication_sql(&Tables(market())).is_err());
+        assert!(config.prepare_publication_sql(&AllTables).is_err());
+
+        let mut config = config.existing_publication();
+        assert!(config.prepare_publication_sql(&Tables(market())).unwrap().is_empty());
+        assert!(config.prepare_publication_sql(&Missing).is_err());
+
+        config.tables = Some(Vec::new());
+        assert!(config.prepare_publication_sql(&AllTables).is_err());
+    }
+
+    #[test]
+    fn existing_publication_from_rows() {
+        use ExistingPublication::*;
+
+        assert_eq!(ExistingPublication::from_rows([]), Missing);
+        let rows = [(true, Some("Market".to_string())), (true, Some("Other".to_string()))];
+        assert_eq!(ExistingPublication::from_rows(rows), AllTables);
+        assert_eq!(ExistingPublication::from_rows([(false, None)]), Tables(Vec::new()));
+        let rows = [(false, Some("Market".to_string()))];
+        assert_eq!(ExistingPublication::from_rows(rows), Tables(vec!["Market".to_string()]));
+    }
+}
@@ -0,0 +1,98 @@
+use std::{collections::HashSet, hash::Hash, ops::Deref, sync::atomic::Ordering};
+
+use crate::{
+    core::{defs::Index, TableValues},
+    replication::TableFromTupleData,
+    Db, Error, Time, Value, ValueProvider,
+};
+
+/// What-if scenario on top of a [`Db`], created by [`Db::scenario`].
+///
+/// Values are overridden in an overlay, without touching the value provider of the live
+/// database. Functions are evaluated against the scenario through its [`Deref`] to a
+/// [`Db`], e.g. `cEleCzk(&scenario, t)`. The scenario has its own cache, but reuses the
+/// cached results of the live database that do not depend on any overridden value, and
+/// it records its dependencies in the shared dependency graph.
+///
+/// The live database cannot be updated while the scenario exists. Drop the scenario (or
+/// call [`Scenario::discard`]) to throw the overrides away, or commit them with
+/// `db.update_many(scenario.into_updates())`.
+pub struct Scenario<'a, Sel, T, VP>
+where
+    Sel: Clone + Eq + Hash,
+    T: TableFromTupleData + TableValues<Sel>,
+    VP: ValueProvider<Sel>,
+{
+    db: Db<Sel, T, VP>,
+    base: &'a Db<Sel, T, VP>,
+}
+
+impl<'a, Sel, T, VP> Scenario<'a, Sel, T, VP>
+where
+    Sel: Clone + Eq + Hash,
+    T: TableFromTupleData + TableValues<Sel>,
+    VP: ValueProvider<Sel>,
+{
+    pub(crate) fn new(base: &'a Db<Sel, T, VP>, as_of: Option<Time>) -> Self {
+        Scenario {
+            db: base.scenario_db(as_of),
+            base,
+        }
+    }
+
+    /// Override a value in the scenario.
+    ///
+    /// Returns the nodes invalidated in the scenario, like [`Db::update`].
+    pub fn update(
+        &mut self,
+        name: &'static str,
+        selector: &Sel,
+        t: &Time,
+        new_value: impl Into<Value>,
+    ) -> Result<HashSet<Index>, Error> {
+        self.db.update(name, selector, t, new_value)
+    }
+
+    /// Override several values at once, like [`Db::update_many`].
+    pub fn update_many<V: Into<Value>>(
+        &mut self,
+        values: impl IntoIterator<Item = (&'static str, Sel, Time, V)>,
+    ) -> Result<HashSet<Index>, Error> {
+        self.db.update_many(values)
+    }
+
+    /// Throw the overrides away.
+    pub fn discard(self) {}
+
+    /// The overridden values, to be applied to the live database with
+    /// [`Db::update_many`].
+    pub fn into_updates(mut self) -> Vec<(&'static str, Sel, Time, Value)> {
+        self.db.take_overrides()
+    }
+}
+
+impl<Sel, T, VP> Deref for Scenario<'_, Sel, T, VP>
+where
+    Sel: Clone + Eq + Hash,
+    T: TableFromTupleData + TableValues<Sel>,
+    VP: ValueProvider<Sel>,
+{
+    type Target = Db<Sel, T, VP>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.db
+    }
+}
+
+impl<Sel, T, VP> Drop for Scenario<'_, Sel, T, VP>
+where
+    Sel: Clone + Eq + Hash,
+    T: TableFromTupleData + TableValues<Sel>,
+    VP: ValueProvider<Sel>,
+{
+    fn drop(&mut self) {
+        // The nodes added to the shared graph by the scenario are pruned by the next
+        // update of the live database.
+        self.base.needs_pruning().store(true, Ordering::Release);
+    }
+}
@@ -0,0 +1,86 @@
+use std::{hash::Hash, sync::Arc};
+
+use tokio::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{
+    core::TableValues, db::SubscriptionId, replication::TableFromTupleData, Db, Error, Value,
+    ValueProvider,
+};
+
+/// A [`Db`] that can be shared between tokio tasks and threads.
+///
+/// Any number of tasks can evaluate functions at the same time through [`SharedDb::read`];
+/// every evaluation tracks its dependencies separately. Updates and replication take the
+/// write lock only for the time needed to apply the changes.
+pub struct SharedDb<Sel, T, VP>
+where
+    Sel: Clone + Eq + Hash,
+    T: TableFromTupleData + TableValues<Sel>,
+    VP: ValueProvider<Sel>,
+{
+    db: Arc<RwLock<Db<Sel, T, VP>>>,
+    sync_lock: Arc<Mutex<()>>,
+}
+
+impl<Sel, T, VP> Clone for SharedDb<Sel, T, VP>
+where
+    Sel: Clone + Eq + Hash,
+    T: TableFromTupleData + TableValues<Sel>,
+    VP: ValueProvider<Sel>,
+{
+    fn clone(&self) -> Self {
+        SharedDb {
+            db: self.db.clone(),
+            sync_lock: self.sync_lock.clone(),
+        }
+    }
+}
+
+impl<Sel, T, VP> SharedDb<Sel, T, VP>
+where
+    Sel: Clone + Eq + Hash,
+    T: TableFromTupleData + TableValues<Sel>,
+    VP: ValueProvider<Sel>,
+{
+    pub fn new(db: Db<Sel, T, VP>) -> Self {
+        SharedDb {
+            db: Arc::new(RwLock::new(db)),
+            sync_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub async fn read(&self) -> RwLockReadGuard<'_, Db<Sel, T, VP>> {
+        self.db.read().await
+    }
+
+    pub async fn write(&self) -> RwLockWriteGuard<'_, Db<Sel, T, VP>> {
+        self.db.write().await
+    }
+
+    /// Same as [`Db::sync_changes`], but readers are blocked only while the changes are
+    /// applied, not while they are fetched from the database.
+    ///
+    /// Like [`Db::sync_changes`], this polls for the changes. With a
+    /// [`ReplicationStream`](crate::ReplicationStream), apply each transaction under
+    /// [`SharedDb::write`] instead.
+    pub async fn sync_changes(&self) -> Result<Vec<(SubscriptionId, Value, Value)>, Error> {
+        // Keep the fetched batches in order when several tasks sync at the same time.
+        let _sync_guard = self.sync_lock.lock().await;
+
+        let changes = self.db.read().await.grab_changes().await?;
+        let result = self.db.write().await.apply_changes(changes);
+        self.db.read().await.confirm_changes().await?;
+        result
+    }
+}
+
+impl<Sel, T, VP> From<Db<Sel, T, VP>> for SharedDb<Sel, T, VP>
+where
+    Sel: Clone + Eq + Hash,
+    T: TableFromTupleData + TableValues<Sel>,
+    VP: ValueProvider<Sel>,
+{
+    fn from(db: Db<Sel, T, VP>) -> Self {
+        SharedDb::new(db)
+    }
+}
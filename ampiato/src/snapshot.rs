@@ -0,0 +1,103 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::Error;
+
+/// Bumped whenever the layout of the snapshot changes.
+const SNAPSHOT_VERSION: u32 = 2;
+
+/// Loaded values of a value provider together with the position in the replication
+/// stream they reflect.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Snapshot<VP> {
+    version: u32,
+    /// End LSN of the last applied transaction, `None` if no change was replicated yet.
+    pub lsn: Option<u64>,
+    pub value_provider: VP,
+}
+
+/// Write the snapshot to a temporary file first and then move it over `path`, so that a
+/// crash never leaves a truncated snapshot behind. Returns once the snapshot is on disk.
+pub(crate) fn write_snapshot<VP: Serialize>(
+    path: &Path,
+    lsn: Option<u64>,
+    value_provider: &VP,
+) -> Result<(), Error> {
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        lsn,
+        value_provider,
+    };
+    let tmp_path = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp_path).map_err(snapshot_error)?);
+    bincode::serialize_into(&mut writer, &snapshot).map_err(snapshot_error)?;
+    let file = writer.into_inner().map_err(snapshot_error)?;
+    file.sync_all().map_err(snapshot_error)?;
+    std::fs::rename(&tmp_path, path).map_err(snapshot_error)?;
+    sync_parent_dir(path)
+}
+
+/// Make the rename of a file durable.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> Result<(), Error> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(snapshot_error)
+}
+
+/// Directories cannot be opened for syncing on other platforms.
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> Result<(), Error> {
+    Ok(())
+}
+
+pub(crate) fn read_snapshot<VP: DeserializeOwned>(path: &Path) -> Result<Snapshot<VP>, Error> {
+    let reader = BufReader::new(File::open(path).map_err(snapshot_error)?);
+    let snapshot: Snapshot<VP> = bincode::deserialize_from(reader).map_err(snapshot_error)?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(Error::SnapshotError(format!(
+            "Unsupported snapshot version {}, expected {}",
+            snapshot.version, SNAPSHOT_VERSION
+        )));
+    }
+    Ok(snapshot)
+}
+
+fn snapshot_error(error: impl std::fmt::Display) -> Error {
+    Error::SnapshotError(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{Time, TimeSeriesChanges};
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut series = TimeSeriesChanges::default();
+        series.push(Time(0), 1.5);
+        series.push(Time(900), 2.5);
+        let values = HashMap::from([(1_i64, series)]);
+
+        let path = std::env::temp_dir().join(format!("ampiato-{}.snapshot", std::process::id()));
+        write_snapshot(&path, Some(42), &values).unwrap();
+        let snapshot: Snapshot<HashMap<i64, TimeSeriesChanges<f64>>> =
+            read_snapshot(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(snapshot.lsn, Some(42));
+        assert_eq!(snapshot.value_provider[&1].get(&Time(900)), Some(2.5));
+        assert!(read_snapshot::<()>(&path).is_err());
+    }
+}
@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::core::defs::Time;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct TimeSeriesChanges<V: Clone> {
     index: Vec<Time>,
     values: Vec<V>,
@@ -37,9 +39,28 @@ impl<V: Clone + std::fmt::Debug> TimeSeriesChanges<V> {
         }
         Some(self.values[idx].clone())
     }
+
+    /// Remove the value set at the given time.
+    pub fn remove(&mut self, time: &Time) -> Option<V> {
+        let idx = bisection::bisect_left(&self.index, time);
+        if idx == self.index.len() || self.index[idx] != *time {
+            return None;
+        }
+        self.index.remove(idx);
+        Some(self.values.remove(idx))
+    }
+
+    /// The value set at the given time or the latest one before it.
+    pub fn value_at(&self, time: &Time) -> Option<V> {
+        let idx = bisection::bisect_right(&self.index, time);
+        if idx == 0 {
+            return None;
+        }
+        Some(self.values[idx - 1].clone())
+    }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct TimeSeriesDense<V: Clone> {
     index: Vec<Time>,
     values: Vec<V>,
@@ -76,6 +97,62 @@ impl<V: Clone> TimeSeriesDense<V> {
         }
         Some(self.values[idx].clone())
     }
+
+    pub fn remove(&mut self, time: &Time) -> Option<V> {
+        let idx = bisection::bisect_left(&self.index, time);
+        if idx == self.index.len() || self.index[idx] != *time {
+            return None;
+        }
+        self.index.remove(idx);
+        Some(self.values.remove(idx))
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Time, &V)> {
+        self.index.iter().copied().zip(self.values.iter())
+    }
+
+    pub fn values(&self) -> &[V] {
+        &self.values
+    }
+}
+
+impl<V: Clone> IntoIterator for TimeSeriesChanges<V> {
+    type Item = (Time, V);
+    type IntoIter = std::iter::Zip<std::vec::IntoIter<Time>, std::vec::IntoIter<V>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.index.into_iter().zip(self.values)
+    }
+}
+
+impl<V: Clone> IntoIterator for TimeSeriesDense<V> {
+    type Item = (Time, V);
+    type IntoIter = std::iter::Zip<std::vec::IntoIter<Time>, std::vec::IntoIter<V>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.index.into_iter().zip(self.values)
+    }
+}
+
+impl<V: Clone> FromIterator<(Time, V)> for TimeSeriesDense<V> {
+    fn from_iter<I: IntoIterator<Item = (Time, V)>>(iter: I) -> Self {
+        let mut series = TimeSeriesDense {
+            index: Vec::new(),
+            values: Vec::new(),
+        };
+        for (time, value) in iter {
+            series.push(time, value);
+        }
+        series
+    }
 }
 
 pub struct TimeSeriesInterval<V: Clone> {
@@ -109,6 +186,9 @@ mod tests {
         assert_eq!(ts.get(&Time(2)), Some(2.0));
         assert_eq!(ts.get(&Time(3)), Some(3.0));
         assert_eq!(ts.get(&Time(4)), Some(3.0));
+        assert_eq!(ts.value_at(&Time(0)), None);
+        assert_eq!(ts.value_at(&Time(2)), Some(2.0));
+        assert_eq!(ts.value_at(&Time(4)), Some(3.0));
     }
     #[test]
     fn simple_time_series_dense() {
@@ -122,5 +202,10 @@ mod tests {
         assert_eq!(ts.get(&Time(2)), Some(2.0));
         assert_eq!(ts.get(&Time(3)), Some(3.0));
         assert_eq!(ts.get(&Time(4)), None);
+
+        assert_eq!(ts.remove(&Time(2)), Some(2.0));
+        assert_eq!(ts.remove(&Time(2)), None);
+        assert_eq!(ts.get(&Time(2)), None);
+        assert_eq!(ts.into_iter().collect::<Vec<_>>(), [(Time(1), 1.0), (Time(3), 3.0)]);
     }
 }
@@ -1,8 +1,34 @@
-use crate::core::defs::Time;
+use crate::core::{defs::Time, value::Value, Error};
 
 pub trait ValueProvider<Sel>: Sized {
     fn from_pool(pool: &sqlx::PgPool) -> impl std::future::Future<Output = Self> + Send;
-    fn set_value(&mut self, name: &'static str, selector: Sel, t: Time, value: f64);
-    fn get_value(&self, name: &'static str, selector: &Sel, t: &Time) -> f64;
-    fn get_value_opt(&self, name: &'static str, selector: &Sel, t: &Time) -> Option<f64>;
+    /// Set a value, or return [`Error::UnexpectedValueType`] if it has a different type
+    /// than the quantity.
+    fn set_value(
+        &mut self,
+        name: &'static str,
+        selector: Sel,
+        t: Time,
+        value: Value,
+    ) -> Result<(), Error>;
+    fn get_value(&self, name: &'static str, selector: &Sel, t: &Time) -> Value;
+    fn get_value_opt(&self, name: &'static str, selector: &Sel, t: &Time) -> Option<Value>;
+
+    /// Mark the value as explicitly cleared, e.g. after its column was set to NULL.
+    ///
+    /// Unlike a removed value, a cleared value of a time series of changes hides the
+    /// earlier values from `t` on.
+    fn clear_value(&mut self, name: &'static str, selector: Sel, t: Time);
+
+    /// Remove a value, e.g. after its row was deleted. Returns the removed value.
+    fn remove_value(&mut self, name: &'static str, selector: &Sel, t: &Time) -> Option<Value>;
+
+    /// Remove all values loaded from a table, e.g. after it was truncated. Returns the
+    /// removed values.
+    fn remove_table(&mut self, table_name: &str) -> Vec<(&'static str, Sel, Time, Value)>;
+
+    /// Table and column the quantity is stored in.
+    fn quantity_source(&self, _name: &'static str) -> Option<(&'static str, &'static str)> {
+        None
+    }
 }
@@ -0,0 +1,18 @@
+from django.db import migrations
+
+
+class Migration(migrations.Migration):
+
+    dependencies = [
+        ("ampiatomigrations", "0003_alter_blokdef_idblokdef"),
+    ]
+
+    # Deleted rows are replicated with all their columns, so that their values can be
+    # removed.
+    operations = [
+        migrations.RunSQL(
+            sql=f'ALTER TABLE "{table}" REPLICA IDENTITY FULL;',
+            reverse_sql=f'ALTER TABLE "{table}" REPLICA IDENTITY DEFAULT;',
+        )
+        for table in ["BlokVykon", "BlokVS", "Market"]
+    ]
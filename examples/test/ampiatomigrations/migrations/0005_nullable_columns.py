@@ -0,0 +1,24 @@
+from django.db import migrations, models
+
+
+class Migration(migrations.Migration):
+
+    dependencies = [
+        ("ampiatomigrations", "0004_replica_identity_full"),
+    ]
+
+    operations = [
+        migrations.AlterField(
+            model_name=model_name,
+            name=name,
+            field=models.FloatField(null=True),
+        )
+        for model_name, name in [
+            ("blokvykon", "pInst"),
+            ("blokvykon", "pDos"),
+            ("blokvykon", "pMin"),
+            ("blokvs", "Abs"),
+            ("market", "CzkEur"),
+            ("market", "cEle"),
+        ]
+    ]
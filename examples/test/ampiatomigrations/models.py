@@ -15,9 +15,9 @@ class BlokDef(models.Model):
 class BlokVykon(models.Model):
     IdBlokDef = models.ForeignKey(BlokDef, db_column="IdBlokDef", on_delete=models.CASCADE)
     Time = models.DateTimeField()
-    pInst = models.FloatField()
-    pDos = models.FloatField()
-    pMin = models.FloatField()
+    pInst = models.FloatField(null=True)
+    pDos = models.FloatField(null=True)
+    pMin = models.FloatField(null=True)
 
     class Meta:
         db_table = "BlokVykon"
@@ -27,7 +27,7 @@ class BlokVykon(models.Model):
 class BlokVS(models.Model):
     IdBlokDef = models.ForeignKey(BlokDef, db_column="IdBlokDef", on_delete=models.CASCADE)
     Time = models.DateTimeField()
-    Abs = models.FloatField()
+    Abs = models.FloatField(null=True)
 
     class Meta:
         db_table = "BlokVS"
@@ -36,8 +36,8 @@ class BlokVS(models.Model):
 
 class Market(models.Model):
     Time = models.DateTimeField()
-    CzkEur = models.FloatField()
-    cEle = models.FloatField()
+    CzkEur = models.FloatField(null=True)
+    cEle = models.FloatField(null=True)
 
     class Meta:
         db_table = "Market"
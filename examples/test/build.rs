@@ -1,4 +1,3 @@
-use dotenv;
 // Example custom build script.
 fn main() {
     dotenv::dotenv().ok();
@@ -25,12 +25,14 @@ DATATYPE_TO_DJANGO = {
     "int": "IntegerField",
     "float": "FloatField",
     "string": "TextField",
+    "bool": "BooleanField",
 }
 
 DATATYPE_TO_RUST = {
     "int": "i64",
     "float": "f64",
     "string": "String",
+    "bool": "bool",
 }
 
 
@@ -224,7 +226,7 @@ def serialize_table(table: Table) -> list[str]:
     for sel in table.selector.fields:
         lines.append(f"    {sel} = {sel.field_definition()}")
     for column in table.columns:
-        lines.append(f"    {column.name} = models.{column.data_type}()")
+        lines.append(f"    {column.name} = models.{column.data_type}(null=True)")
     lines += [
         "",
         "    class Meta:",
@@ -315,7 +317,7 @@ def setup_django():
 
 def render_selector(db: QuantityDb) -> list[str]:
     lines = [
-        "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]",
+        "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]",
         "pub enum Selector {",
     ]
     selectors = list(sorted({t.selector for t in db.tables}))
@@ -332,13 +334,14 @@ def render_selector(db: QuantityDb) -> list[str]:
 
 
 def rust_storage_type(table: Table, column: Column) -> str:
-    time_series_type = f"TimeSeries{table.time_repr}<{column.data_type.rust}>"
+    # `None` marks a value cleared by NULL.
+    time_series_type = f"TimeSeries{table.time_repr}<Option<{column.data_type.rust}>>"
     return f"HashMap<Selector, {time_series_type}>"
 
 
 def render_entity(entity: Entity) -> list[str]:
     lines = [
-        "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, sqlx::Type)]",
+        "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, sqlx::Type, serde::Serialize, serde::Deserialize)]",
         f"pub struct {entity.name}(i64);",
         "",
         f"impl pgoutput::EntityRef for {entity.name} {{",
@@ -358,7 +361,7 @@ def render_entity(entity: Entity) -> list[str]:
         "}",
 
         "",
-        "#[derive(Debug, Clone, sqlx::FromRow)]",
+        "#[derive(Debug, Clone, sqlx::FromRow, serde::Serialize, serde::Deserialize)]",
         f"pub struct {entity.name}Def {{",
         f"    pub Id{entity.name}Def: i64,",
     ]
@@ -387,7 +390,7 @@ def create_table_query(table: Table, indent: int | None = None) -> str:
     column_fields = ",\n        ".join(f'"{c.name}"' for c in table.columns)
     query = dedent(f"""
     SELECT
-        {selector_fields}EXTRACT(EPOCH FROM "Time")::BIGINT AS "Time",
+        {selector_fields}(EXTRACT(EPOCH FROM "Time") * 1000000)::BIGINT AS "Time",
         {column_fields}
     FROM
         "{table.name}"
@@ -416,7 +419,7 @@ def render_table(table: Table) -> list[str]:
 
     for column in table.columns:
         lines += [
-            f"    pub {column.name}: {column.data_type.rust},",
+            f"    pub {column.name}: Option<{column.data_type.rust}>,",
         ]
 
     selector_no_time = table.selector.fields[:-1]
@@ -438,7 +441,7 @@ def render_table(table: Table) -> list[str]:
     for sel in table.selector.fields:
         if sel.is_time:
             lines += [
-                f'            {sel.raw_str}: Time(row.try_get("{sel.raw_str}")?),',
+                f'            {sel.raw_str}: Time::from_timestamp_micros(row.try_get("{sel.raw_str}")?),',
             ]
         else:
             lines += [
@@ -480,7 +483,6 @@ def render_table(table: Table) -> list[str]:
         lines += [
             f'            "{column.name}",',
         ]
-    n_cols = len(table.columns) + len(table.selector.fields) + 1
     lines += [
         "        ]",
         "    }",
@@ -491,27 +493,18 @@ def render_table(table: Table) -> list[str]:
         "}",
         "",
         f"impl FromTupleData for {table.name} {{",
-        "    fn from_tuple_data(tuple_data: &pgoutput::TupleData) -> Result<Self, Error> {",
-        f"        if tuple_data.number_of_columns != {n_cols} {{",
-        "            return Err(Error::UnexpectedNumberOfColumns {",
-        "                actual: tuple_data.number_of_columns as usize,",
-        f"                expected: {n_cols},",
-        "            });",
-        "        }",
-        "",
+        "    fn from_tuple_data(row: &TupleRow) -> Result<Self, Error> {",
         "        Ok(Self {",
     ]
-    i = 0
     for sel in table.selector.fields:
+        column_name = sel.raw_str if sel.is_time else f"Id{sel.raw_str}Def"
         lines += [
-            f'            {sel.raw_str}: Decode::decode(&tuple_data.columns[{i}])?,',
+            f'            {sel.raw_str}: row.decode("{column_name}")?,',
         ]
-        i += 1
     for column in table.columns:
         lines += [
-            f'            {column.name}: Decode::decode(&tuple_data.columns[{i}])?,',
+            f'            {column.name}: row.decode("{column.name}")?,',
         ]
-        i += 1
     lines += [
         "        })",
         "    }",
@@ -526,12 +519,14 @@ def render_table(table: Table) -> list[str]:
         f"        Selector::{table.selector.rust_variant()}({selector_fields})",
         "    }",
         "",
-        "    fn values(&self) -> Vec<(&'static str, &f64)> {",
+        "    fn values(&self) -> Vec<(&'static str, Option<Value>)> {",
         "        vec![",
     ]
     for column in table.columns:
+        # Strings are categorical values, the other types are `Copy`.
+        clone = ".clone()" if column.data_type.rust == "String" else ""
         lines += [
-            f'            ("{table.name}{column.name}", &self.{column.name}),',
+            f'            ("{table.name}{column.name}", self.{column.name}{clone}.map(Value::from)),',
         ]
     lines += [
         "        ]",
@@ -540,13 +535,6 @@ def render_table(table: Table) -> list[str]:
         "}",
     ]
 
-    # if tuple_data.number_of_columns != 3 {
-    #         return Err(Box::new(Error::UnexpectedNumberOfColumns {
-    #             actual: tuple_data.number_of_columns as usize,
-    #             expected: 3,
-    #         }));
-    #     }
-
     return [4 * " " + l for l in lines]
 
 
@@ -566,17 +554,28 @@ def render_table_enum(db: QuantityDb) -> list[str]:
         "}",
         "",
         "impl TableFromTupleData for Table {",
-        "    fn from_tuple_data(relation_name: &str, tuple_data: &pgoutput::TupleData) -> Result<Self, Error> {",
-        "        match relation_name {",
+        "    fn from_tuple_data(row: &TupleRow) -> Result<Self, Error> {",
+        "        match row.relation_name() {",
     ]
     for table in db.tables:
         lines += [
-            f'            "{table.name}" => Ok(Table::{table.name}(tables::{table.name}::from_tuple_data(tuple_data)?)),',
+            f'            "{table.name}" => Ok(Table::{table.name}(tables::{table.name}::from_tuple_data(row)?)),',
         ]
     lines += [
         '            table_name => Err(Error::UnknownTable { table_name: table_name.to_string() }),',
         "        }",
         "    }",
+        "",
+        "    fn table_names() -> Vec<&'static str> {",
+        "        vec![",
+    ]
+    for table in db.tables:
+        lines += [
+            f"            tables::{table.name}::table_name(),",
+        ]
+    lines += [
+        "        ]",
+        "    }",
         "}",
         "",
         "impl TableValues<Selector> for Table {",
@@ -602,7 +601,7 @@ def render_table_enum(db: QuantityDb) -> list[str]:
         "        }",
         "    }",
         "",
-        "    fn values(&self) -> Vec<(&'static str, &f64)> {",
+        "    fn values(&self) -> Vec<(&'static str, Option<Value>)> {",
         "        match self {",
     ]
     for table in db.tables:
@@ -629,7 +628,7 @@ def render_table_mod(table: Table) -> list[str]:
 
     lines += [
         "    use super::{Selector, Db, Blok};",
-        "    use ampiato::Time;",
+        "    use ampiato::{Error, Time};",
         "",
     ]
 
@@ -647,7 +646,11 @@ def render_table_mod(table: Table) -> list[str]:
             )
         lines += [
             f"    pub fn {column.name}(db: &Db, {selector_args} t: Time) -> {column.data_type.rust} {{",
-            f'        db.get_value("{table.name}{column.name}", Selector::{table.selector.rust_variant()}({selector_variant_args}), t)',
+            f'        db.get_value_as("{table.name}{column.name}", Selector::{table.selector.rust_variant()}({selector_variant_args}), t)',
+            "    }",
+            "",
+            f"    pub fn try_{column.name}(db: &Db, {selector_args} t: Time) -> Result<{column.data_type.rust}, Error> {{",
+            f'        db.try_get_value_as("{table.name}{column.name}", Selector::{table.selector.rust_variant()}({selector_variant_args}), t)',
             "    }",
             "",
         ]
@@ -667,7 +670,11 @@ def render_prelude(db: QuantityDb) -> list[str]:
     ]
 
     for table in db.tables:
-        qtys = ", ".join(column.name for column in table.columns)
+        qtys = ", ".join(
+            name
+            for column in table.columns
+            for name in (column.name, f"try_{column.name}")
+        )
         lines += [
             f"    pub use super::{table.name}::{{{qtys}}};",
         ]
@@ -684,7 +691,7 @@ def render_value_provider_class(db: QuantityDb) -> list[str]:
     lines = []
 
     lines += [
-        "#[derive(Debug)]",
+        "#[derive(Debug, serde::Serialize, serde::Deserialize)]",
         "pub struct ValueProvider {",
     ]
     for entity in db.entities:
@@ -736,13 +743,13 @@ def render_value_provider_class(db: QuantityDb) -> list[str]:
         ]
     lines += [
         "",
-        "    fn _get_value_impl(&self, name: &'static str, selector: &Selector, t: &Time) -> Option<f64> {",
+        "    fn _get_value_impl(&self, name: &'static str, selector: &Selector, t: &Time) -> Option<Value> {",
         "        match name {",
     ]
     for table in db.tables:
         for column in table.columns:
             lines += [
-                f'            "{table.name}{column.name}" => self.{table.name}{column.name}.get(selector)?.get(t),',
+                f'            "{table.name}{column.name}" => self.{table.name}{column.name}.get(selector)?.get(t).flatten().map(Value::from),',
             ]
     lines += [
         '            _ => panic!("Unknown quantity {}", name),',
@@ -755,18 +762,33 @@ def render_value_provider_class(db: QuantityDb) -> list[str]:
     lines += [
         "impl ampiato::ValueProvider<Selector> for ValueProvider {",
         "    async fn from_pool(pool: &sqlx::PgPool) -> Self {",
-        "        let vp = load_value_provider(pool).await;",
-        "        vp",
+        "        load_value_provider(pool).await",
+        "    }",
+        "",
+        "    fn set_value(&mut self, name: &'static str, selector: Selector, t: Time, value: Value) -> Result<(), Error> {",
+        "        match name {",
+    ]
+
+    for table in db.tables:
+        for column in table.columns:
+            lines += [
+                f'            "{table.name}{column.name}" => self.{table.name}{column.name}.entry(selector).or_default().set(&t, Some(value.typed(name)?)),',
+            ]
+
+    lines += [
+        '            name => panic!("Unknown quantity {}", name),',
+        "        }",
+        "        Ok(())",
         "    }",
         "",
-        "    fn set_value(&mut self, name: &'static str, selector: Selector, t: Time, value: f64) {",
+        "    fn clear_value(&mut self, name: &'static str, selector: Selector, t: Time) {",
         "        match name {",
     ]
 
     for table in db.tables:
         for column in table.columns:
             lines += [
-                f'            "{table.name}{column.name}" => self.{table.name}{column.name}.entry(selector).or_default().set(&t, value),',
+                f'            "{table.name}{column.name}" => self.{table.name}{column.name}.entry(selector).or_default().set(&t, None),',
             ]
 
     lines += [
@@ -777,20 +799,69 @@ def render_value_provider_class(db: QuantityDb) -> list[str]:
     ]
 
     additional_code = """
-    fn get_value(&self, name: &'static str, selector: &Selector, t: &Time) -> f64 {
+    fn get_value(&self, name: &'static str, selector: &Selector, t: &Time) -> Value {
         match self._get_value_impl(name, selector, t) {
             Some(v) => v,
             None => panic!("Value not found: {}({:?})", name, selector),
         }
     }
 
-    fn get_value_opt(&self, name: &'static str, selector: &Selector, t: &Time) -> Option<f64> {
+    fn get_value_opt(&self, name: &'static str, selector: &Selector, t: &Time) -> Option<Value> {
         self._get_value_impl(name, selector, t)
     }
     """
     lines += additional_code.split("\n")
 
     lines += [
+        "",
+        "    fn remove_value(&mut self, name: &'static str, selector: &Selector, t: &Time) -> Option<Value> {",
+        "        match name {",
+    ]
+    for table in db.tables:
+        for column in table.columns:
+            lines += [
+                f'            "{table.name}{column.name}" => self.{table.name}{column.name}.get_mut(selector)?.remove(t).flatten().map(Value::from),',
+            ]
+    lines += [
+        '            _ => panic!("Unknown quantity {}", name),',
+        "        }",
+        "    }",
+        "",
+        "    fn remove_table(&mut self, table_name: &str) -> Vec<(&'static str, Selector, Time, Value)> {",
+        "        let mut removed = Vec::new();",
+        "        match table_name {",
+    ]
+    for table in db.tables:
+        lines += [
+            f'            "{table.name}" => {{',
+        ]
+        for column in table.columns:
+            lines += [
+                f"                for (selector, series) in std::mem::take(&mut self.{table.name}{column.name}) {{",
+                f'                    removed.extend(series.into_iter().filter_map(|(t, v)| Some(("{table.name}{column.name}", selector, t, Value::from(v?)))));',
+                "                }",
+            ]
+        lines += [
+            "            }",
+        ]
+    lines += [
+        "            _ => {}",
+        "        }",
+        "        removed",
+        "    }",
+        "",
+        "    fn quantity_source(&self, name: &'static str) -> Option<(&'static str, &'static str)> {",
+        "        match name {",
+    ]
+    for table in db.tables:
+        for column in table.columns:
+            lines += [
+                f'            "{table.name}{column.name}" => Some(("{table.name}", "{column.name}")),',
+            ]
+    lines += [
+        "            _ => None,",
+        "        }",
+        "    }",
         "}",
         "",
     ]
@@ -808,7 +879,7 @@ def render_load_value_provider(db: QuantityDb) -> list[str]:
 
     for entity in db.entities:
         lines += [
-            f"    let rows = sqlx::query_as::<_, {entity.name}Def>(&{entity.name}Def::query()).fetch_all(pool).await.unwrap();",
+            f"    let rows = sqlx::query_as::<_, {entity.name}Def>({entity.name}Def::query()).fetch_all(pool).await.unwrap();",
             "    for row in rows {",
             "        vp." + entity.name + ".insert(row.Jmeno.clone(), row);",
             "    }",
@@ -816,11 +887,14 @@ def render_load_value_provider(db: QuantityDb) -> list[str]:
 
     for table in db.tables:
         lines += [
-            f"    let rows = sqlx::query_as::<_, tables::{table.name}>(&tables::{table.name}::query()).fetch_all(pool).await.unwrap();",
+            f"    let rows = sqlx::query_as::<_, tables::{table.name}>(tables::{table.name}::query()).fetch_all(pool).await.unwrap();",
             "     for row in rows {",
             "         let sel = row.selector();",
             "         for (name, value) in row.values() {",
-            "             vp.set_value(name, sel, row.Time, *value);",
+            "             match value {",
+            "                 Some(value) => vp.set_value(name, sel, row.Time, value).unwrap(),",
+            "                 None => vp.clear_value(name, sel, row.Time),",
+            "             }",
             "         }",
             "     }",
         ]
@@ -845,10 +919,10 @@ def render_value_provider(path: Path, db: QuantityDb) -> list[str]:
     use ampiato::replication::pgoutput;
     use ampiato::core::BoxDynError;
     use ampiato::replication::pgoutput::Decode;
-    use ampiato::replication::TableFromTupleData;
+    use ampiato::replication::{TableFromTupleData, TupleRow};
     use ampiato::{Time, TimeSeriesChanges, TimeSeriesDense, ValueProvider as _};
     use ampiato::replication::pgoutput::EntityRef;
-    use ampiato::{Error, TableMetadata, TableValues};
+    use ampiato::{Error, TableMetadata, TableValues, Value};
     use ampiato::FromTupleData;
     use sqlx::Row;
 
@@ -1,5 +1,5 @@
 use ampiato_macro::tem_fn;
-use ampiato::Time;
+use ampiato::{Error, ReplicationConfig, ReplicationSlot, ReplicationStream, Time};
 use value_provider::prelude::*;
 
 mod value_provider;
@@ -7,6 +7,10 @@ mod value_provider;
 // const H: i64 = 3600;
 // const OI: i64 = 15 * 60;
 
+const SNAPSHOT_PATH: &str = "ampiato.snapshot";
+/// Number of transactions applied between two snapshots.
+const SNAPSHOT_INTERVAL: usize = 100;
+
 
 #[tem_fn]
 fn cEleCzk(db: &Db, t: Time) -> f64 {
@@ -18,10 +22,28 @@ fn pMax(db: &Db, b: Blok, t: Time) -> f64 {
     min(pInst(db, b, t), pDos(db, b, t))
 }
 
+#[tem_fn]
+fn pMaxChecked(db: &Db, b: Blok, t: Time) -> Result<f64, Error> {
+    Ok(min(try_pInst(db, b, t)?, try_pDos(db, b, t)?))
+}
+
 #[tokio::main]
 pub async fn main() -> anyhow::Result<()> {
     dotenv::dotenv().ok();
-    let mut db = Db::from_env(true).await?;
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let replication_config = ReplicationConfig::new("ampiato")
+        .tables::<Table>()
+        .slot(ReplicationSlot::Persistent("ampiato".to_string()));
+    let (mut db, mut changes) = if std::path::Path::new(SNAPSHOT_PATH).exists() {
+        let db = Db::from_snapshot(SNAPSHOT_PATH)?;
+        let changes =
+            ReplicationStream::connect(&database_url, &replication_config, db.last_lsn()).await?;
+        (db, changes)
+    } else {
+        // Start replicating before loading the tables, so that no change is missed.
+        let changes = ReplicationStream::connect(&database_url, &replication_config, None).await?;
+        (Db::from_database_url(&database_url, false).await?, changes)
+    };
 
     ampiato::print_banner();
 
@@ -31,15 +53,28 @@ pub async fn main() -> anyhow::Result<()> {
     let p_max= pMax(&db, b, t);
     println!("Na počátku bylo p_max: {}", p_max);
 
+    match pMaxChecked(&db, b, t + 24 * 3600) {
+        Ok(p_max) => println!("Zítra bude p_max: {}", p_max),
+        Err(e) => println!("Zítra nelze p_max spočítat: {}", e),
+    }
 
-    let subscription_id = db.subscribe("pMax", &Selector::Blok(b), &t);
 
-    loop {
-        let updated_subscribers = db.sync_changes().await?;
+    let subscription = db.subscribe(move |db| pMax(db, b, t));
 
-        if updated_subscribers.contains(&subscription_id) {
-            let p_max = pMax(&db, b, t);
-            println!("cEleCzk: {}", p_max);
+    let mut unsaved_transactions = 0;
+    while let Some(transaction) = changes.next_transaction().await {
+        for (id, _, p_max) in db.apply_changes(transaction?)? {
+            if id == subscription.id() {
+                println!("p_max: {}", p_max);
+            }
+        }
+        unsaved_transactions += 1;
+        if unsaved_transactions == SNAPSHOT_INTERVAL {
+            db.save_snapshot(SNAPSHOT_PATH)?;
+            unsaved_transactions = 0;
         }
+        // The slot keeps the changes after the snapshot, which is where a restart resumes.
+        changes.confirm(db.snapshot_lsn()).await?;
     }
+    Ok(())
 }
@@ -6,15 +6,27 @@ use ampiato::core::BoxDynError;
 use ampiato::replication::pgoutput;
 use ampiato::replication::pgoutput::Decode;
 use ampiato::replication::pgoutput::EntityRef;
-use ampiato::replication::TableFromTupleData;
+use ampiato::replication::{TableFromTupleData, TupleRow};
 use ampiato::FromTupleData;
-use ampiato::{Error, TableMetadata, TableValues};
+use ampiato::{Error, TableMetadata, TableValues, Value};
 use ampiato::{Time, TimeSeriesChanges, TimeSeriesDense, ValueProvider as _};
 use sqlx::Row;
 
 pub type Db = ampiato::Db<Selector, Table, ValueProvider>;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, sqlx::Type)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    sqlx::Type,
+    serde::Serialize,
+    serde::Deserialize,
+)]
 pub struct Blok(i64);
 
 impl pgoutput::EntityRef for Blok {
@@ -33,7 +45,7 @@ impl pgoutput::EntityRef for Blok {
     }
 }
 
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow, serde::Serialize, serde::Deserialize)]
 pub struct BlokDef {
     pub IdBlokDef: i64,
     pub Jmeno: String,
@@ -56,15 +68,15 @@ pub mod tables {
         pub Time: Time,
 
         // Columns
-        pub pInst: f64,
-        pub pDos: f64,
-        pub pMin: f64,
+        pub pInst: Option<f64>,
+        pub pDos: Option<f64>,
+        pub pMin: Option<f64>,
     }
     impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for BlokVykon {
         fn from_row(row: &sqlx::postgres::PgRow) -> Result<Self, sqlx::Error> {
             Ok(Self {
                 Blok: Blok(row.try_get("IdBlokDef")?),
-                Time: Time(row.try_get("Time")?),
+                Time: Time::from_timestamp_micros(row.try_get("Time")?),
                 pInst: row.try_get("pInst")?,
                 pDos: row.try_get("pDos")?,
                 pMin: row.try_get("pMin")?,
@@ -77,7 +89,7 @@ pub mod tables {
             r#"            
             SELECT
                 "IdBlokDef",
-                EXTRACT(EPOCH FROM "Time")::BIGINT AS "Time",
+                (EXTRACT(EPOCH FROM "Time") * 1000000)::BIGINT AS "Time",
                 "pInst",
                 "pDos",
                 "pMin"
@@ -100,20 +112,13 @@ pub mod tables {
     }
 
     impl FromTupleData for BlokVykon {
-        fn from_tuple_data(tuple_data: &pgoutput::TupleData) -> Result<Self, Error> {
-            if tuple_data.number_of_columns != 6 {
-                return Err(Error::UnexpectedNumberOfColumns {
-                    actual: tuple_data.number_of_columns as usize,
-                    expected: 6,
-                });
-            }
-
+        fn from_tuple_data(row: &TupleRow) -> Result<Self, Error> {
             Ok(Self {
-                Blok: Decode::decode(&tuple_data.columns[0])?,
-                Time: Decode::decode(&tuple_data.columns[1])?,
-                pInst: Decode::decode(&tuple_data.columns[2])?,
-                pDos: Decode::decode(&tuple_data.columns[3])?,
-                pMin: Decode::decode(&tuple_data.columns[4])?,
+                Blok: row.decode("IdBlokDef")?,
+                Time: row.decode("Time")?,
+                pInst: row.decode("pInst")?,
+                pDos: row.decode("pDos")?,
+                pMin: row.decode("pMin")?,
             })
         }
     }
@@ -127,11 +132,11 @@ pub mod tables {
             Selector::Blok(self.Blok)
         }
 
-        fn values(&self) -> Vec<(&'static str, &f64)> {
+        fn values(&self) -> Vec<(&'static str, Option<Value>)> {
             vec![
-                ("BlokVykonpInst", &self.pInst),
-                ("BlokVykonpDos", &self.pDos),
-                ("BlokVykonpMin", &self.pMin),
+                ("BlokVykonpInst", self.pInst.map(Value::from)),
+                ("BlokVykonpDos", self.pDos.map(Value::from)),
+                ("BlokVykonpMin", self.pMin.map(Value::from)),
             ]
         }
     }
@@ -143,13 +148,13 @@ pub mod tables {
         pub Time: Time,
 
         // Columns
-        pub Abs: f64,
+        pub Abs: Option<f64>,
     }
     impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for BlokVS {
         fn from_row(row: &sqlx::postgres::PgRow) -> Result<Self, sqlx::Error> {
             Ok(Self {
                 Blok: Blok(row.try_get("IdBlokDef")?),
-                Time: Time(row.try_get("Time")?),
+                Time: Time::from_timestamp_micros(row.try_get("Time")?),
                 Abs: row.try_get("Abs")?,
             })
         }
@@ -160,7 +165,7 @@ pub mod tables {
             r#"            
             SELECT
                 "IdBlokDef",
-                EXTRACT(EPOCH FROM "Time")::BIGINT AS "Time",
+                (EXTRACT(EPOCH FROM "Time") * 1000000)::BIGINT AS "Time",
                 "Abs"
             FROM
                 "BlokVS"
@@ -181,18 +186,11 @@ pub mod tables {
     }
 
     impl FromTupleData for BlokVS {
-        fn from_tuple_data(tuple_data: &pgoutput::TupleData) -> Result<Self, Error> {
-            if tuple_data.number_of_columns != 4 {
-                return Err(Error::UnexpectedNumberOfColumns {
-                    actual: tuple_data.number_of_columns as usize,
-                    expected: 4,
-                });
-            }
-
+        fn from_tuple_data(row: &TupleRow) -> Result<Self, Error> {
             Ok(Self {
-                Blok: Decode::decode(&tuple_data.columns[0])?,
-                Time: Decode::decode(&tuple_data.columns[1])?,
-                Abs: Decode::decode(&tuple_data.columns[2])?,
+                Blok: row.decode("IdBlokDef")?,
+                Time: row.decode("Time")?,
+                Abs: row.decode("Abs")?,
             })
         }
     }
@@ -206,8 +204,8 @@ pub mod tables {
             Selector::Blok(self.Blok)
         }
 
-        fn values(&self) -> Vec<(&'static str, &f64)> {
-            vec![("BlokVSAbs", &self.Abs)]
+        fn values(&self) -> Vec<(&'static str, Option<Value>)> {
+            vec![("BlokVSAbs", self.Abs.map(Value::from))]
         }
     }
 
@@ -217,13 +215,13 @@ pub mod tables {
         pub Time: Time,
 
         // Columns
-        pub CzkEur: f64,
-        pub cEle: f64,
+        pub CzkEur: Option<f64>,
+        pub cEle: Option<f64>,
     }
     impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for Market {
         fn from_row(row: &sqlx::postgres::PgRow) -> Result<Self, sqlx::Error> {
             Ok(Self {
-                Time: Time(row.try_get("Time")?),
+                Time: Time::from_timestamp_micros(row.try_get("Time")?),
                 CzkEur: row.try_get("CzkEur")?,
                 cEle: row.try_get("cEle")?,
             })
@@ -234,7 +232,7 @@ pub mod tables {
         fn query() -> &'static str {
             r#"            
             SELECT
-                EXTRACT(EPOCH FROM "Time")::BIGINT AS "Time",
+                (EXTRACT(EPOCH FROM "Time") * 1000000)::BIGINT AS "Time",
                 "CzkEur",
                 "cEle"
             FROM
@@ -256,18 +254,11 @@ pub mod tables {
     }
 
     impl FromTupleData for Market {
-        fn from_tuple_data(tuple_data: &pgoutput::TupleData) -> Result<Self, Error> {
-            if tuple_data.number_of_columns != 4 {
-                return Err(Error::UnexpectedNumberOfColumns {
-                    actual: tuple_data.number_of_columns as usize,
-                    expected: 4,
-                });
-            }
-
+        fn from_tuple_data(row: &TupleRow) -> Result<Self, Error> {
             Ok(Self {
-                Time: Decode::decode(&tuple_data.columns[0])?,
-                CzkEur: Decode::decode(&tuple_data.columns[1])?,
-                cEle: Decode::decode(&tuple_data.columns[2])?,
+                Time: row.decode("Time")?,
+                CzkEur: row.decode("CzkEur")?,
+                cEle: row.decode("cEle")?,
             })
         }
     }
@@ -281,8 +272,11 @@ pub mod tables {
             Selector::Unit(())
         }
 
-        fn values(&self) -> Vec<(&'static str, &f64)> {
-            vec![("MarketCzkEur", &self.CzkEur), ("MarketcEle", &self.cEle)]
+        fn values(&self) -> Vec<(&'static str, Option<Value>)> {
+            vec![
+                ("MarketCzkEur", self.CzkEur.map(Value::from)),
+                ("MarketcEle", self.cEle.map(Value::from)),
+            ]
         }
     }
 }
@@ -294,21 +288,24 @@ pub enum Table {
 }
 
 impl TableFromTupleData for Table {
-    fn from_tuple_data(
-        relation_name: &str,
-        tuple_data: &pgoutput::TupleData,
-    ) -> Result<Self, Error> {
-        match relation_name {
-            "BlokVykon" => Ok(Table::BlokVykon(tables::BlokVykon::from_tuple_data(
-                tuple_data,
-            )?)),
-            "BlokVS" => Ok(Table::BlokVS(tables::BlokVS::from_tuple_data(tuple_data)?)),
-            "Market" => Ok(Table::Market(tables::Market::from_tuple_data(tuple_data)?)),
+    fn from_tuple_data(row: &TupleRow) -> Result<Self, Error> {
+        match row.relation_name() {
+            "BlokVykon" => Ok(Table::BlokVykon(tables::BlokVykon::from_tuple_data(row)?)),
+            "BlokVS" => Ok(Table::BlokVS(tables::BlokVS::from_tuple_data(row)?)),
+            "Market" => Ok(Table::Market(tables::Market::from_tuple_data(row)?)),
             table_name => Err(Error::UnknownTable {
                 table_name: table_name.to_string(),
             }),
         }
     }
+
+    fn table_names() -> Vec<&'static str> {
+        vec![
+            tables::BlokVykon::table_name(),
+            tables::BlokVS::table_name(),
+            tables::Market::table_name(),
+        ]
+    }
 }
 
 impl TableValues<Selector> for Table {
@@ -328,7 +325,7 @@ impl TableValues<Selector> for Table {
         }
     }
 
-    fn values(&self) -> Vec<(&'static str, &f64)> {
+    fn values(&self) -> Vec<(&'static str, Option<Value>)> {
         match self {
             Table::BlokVykon(t) => t.values(),
             Table::BlokVS(t) => t.values(),
@@ -337,21 +334,23 @@ impl TableValues<Selector> for Table {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
 pub enum Selector {
     Blok(Blok),
     Unit(()),
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct ValueProvider {
     Blok: HashMap<String, BlokDef>,
-    BlokVykonpInst: HashMap<Selector, TimeSeriesChanges<f64>>,
-    BlokVykonpDos: HashMap<Selector, TimeSeriesChanges<f64>>,
-    BlokVykonpMin: HashMap<Selector, TimeSeriesChanges<f64>>,
-    BlokVSAbs: HashMap<Selector, TimeSeriesChanges<f64>>,
-    MarketCzkEur: HashMap<Selector, TimeSeriesDense<f64>>,
-    MarketcEle: HashMap<Selector, TimeSeriesDense<f64>>,
+    BlokVykonpInst: HashMap<Selector, TimeSeriesChanges<Option<f64>>>,
+    BlokVykonpDos: HashMap<Selector, TimeSeriesChanges<Option<f64>>>,
+    BlokVykonpMin: HashMap<Selector, TimeSeriesChanges<Option<f64>>>,
+    BlokVSAbs: HashMap<Selector, TimeSeriesChanges<Option<f64>>>,
+    MarketCzkEur: HashMap<Selector, TimeSeriesDense<Option<f64>>>,
+    MarketcEle: HashMap<Selector, TimeSeriesDense<Option<f64>>>,
 }
 
 impl ValueProvider {
@@ -376,14 +375,44 @@ impl ValueProvider {
         Some(Blok(entity_def.IdBlokDef))
     }
 
-    fn _get_value_impl(&self, name: &'static str, selector: &Selector, t: &Time) -> Option<f64> {
+    fn _get_value_impl(&self, name: &'static str, selector: &Selector, t: &Time) -> Option<Value> {
         match name {
-            "BlokVykonpInst" => self.BlokVykonpInst.get(selector)?.get(t),
-            "BlokVykonpDos" => self.BlokVykonpDos.get(selector)?.get(t),
-            "BlokVykonpMin" => self.BlokVykonpMin.get(selector)?.get(t),
-            "BlokVSAbs" => self.BlokVSAbs.get(selector)?.get(t),
-            "MarketCzkEur" => self.MarketCzkEur.get(selector)?.get(t),
-            "MarketcEle" => self.MarketcEle.get(selector)?.get(t),
+            "BlokVykonpInst" => self
+                .BlokVykonpInst
+                .get(selector)?
+                .get(t)
+                .flatten()
+                .map(Value::from),
+            "BlokVykonpDos" => self
+                .BlokVykonpDos
+                .get(selector)?
+                .get(t)
+                .flatten()
+                .map(Value::from),
+            "BlokVykonpMin" => self
+                .BlokVykonpMin
+                .get(selector)?
+                .get(t)
+                .flatten()
+                .map(Value::from),
+            "BlokVSAbs" => self
+                .BlokVSAbs
+                .get(selector)?
+                .get(t)
+                .flatten()
+                .map(Value::from),
+            "MarketCzkEur" => self
+                .MarketCzkEur
+                .get(selector)?
+                .get(t)
+                .flatten()
+                .map(Value::from),
+            "MarketcEle" => self
+                .MarketcEle
+                .get(selector)?
+                .get(t)
+                .flatten()
+                .map(Value::from),
             _ => panic!("Unknown quantity {}", name),
         }
     }
@@ -391,126 +420,301 @@ impl ValueProvider {
 
 impl ampiato::ValueProvider<Selector> for ValueProvider {
     async fn from_pool(pool: &sqlx::PgPool) -> Self {
-        let vp = load_value_provider(pool).await;
-        vp
+        load_value_provider(pool).await
     }
 
-    fn set_value(&mut self, name: &'static str, selector: Selector, t: Time, value: f64) {
+    fn set_value(
+        &mut self,
+        name: &'static str,
+        selector: Selector,
+        t: Time,
+        value: Value,
+    ) -> Result<(), Error> {
         match name {
             "BlokVykonpInst" => self
                 .BlokVykonpInst
                 .entry(selector)
                 .or_default()
-                .set(&t, value),
+                .set(&t, Some(value.typed(name)?)),
             "BlokVykonpDos" => self
                 .BlokVykonpDos
                 .entry(selector)
                 .or_default()
-                .set(&t, value),
+                .set(&t, Some(value.typed(name)?)),
             "BlokVykonpMin" => self
                 .BlokVykonpMin
                 .entry(selector)
                 .or_default()
-                .set(&t, value),
-            "BlokVSAbs" => self.BlokVSAbs.entry(selector).or_default().set(&t, value),
+                .set(&t, Some(value.typed(name)?)),
+            "BlokVSAbs" => self
+                .BlokVSAbs
+                .entry(selector)
+                .or_default()
+                .set(&t, Some(value.typed(name)?)),
             "MarketCzkEur" => self
                 .MarketCzkEur
                 .entry(selector)
                 .or_default()
-                .set(&t, value),
-            "MarketcEle" => self.MarketcEle.entry(selector).or_default().set(&t, value),
+                .set(&t, Some(value.typed(name)?)),
+            "MarketcEle" => self
+                .MarketcEle
+                .entry(selector)
+                .or_default()
+                .set(&t, Some(value.typed(name)?)),
+            name => panic!("Unknown quantity {}", name),
+        }
+        Ok(())
+    }
+
+    fn clear_value(&mut self, name: &'static str, selector: Selector, t: Time) {
+        match name {
+            "BlokVykonpInst" => self
+                .BlokVykonpInst
+                .entry(selector)
+                .or_default()
+                .set(&t, None),
+            "BlokVykonpDos" => self
+                .BlokVykonpDos
+                .entry(selector)
+                .or_default()
+                .set(&t, None),
+            "BlokVykonpMin" => self
+                .BlokVykonpMin
+                .entry(selector)
+                .or_default()
+                .set(&t, None),
+            "BlokVSAbs" => self.BlokVSAbs.entry(selector).or_default().set(&t, None),
+            "MarketCzkEur" => self.MarketCzkEur.entry(selector).or_default().set(&t, None),
+            "MarketcEle" => self.MarketcEle.entry(selector).or_default().set(&t, None),
             name => panic!("Unknown quantity {}", name),
         }
     }
 
-    fn get_value(&self, name: &'static str, selector: &Selector, t: &Time) -> f64 {
+    fn get_value(&self, name: &'static str, selector: &Selector, t: &Time) -> Value {
         match self._get_value_impl(name, selector, t) {
             Some(v) => v,
             None => panic!("Value not found: {}({:?})", name, selector),
         }
     }
 
-    fn get_value_opt(&self, name: &'static str, selector: &Selector, t: &Time) -> Option<f64> {
+    fn get_value_opt(&self, name: &'static str, selector: &Selector, t: &Time) -> Option<Value> {
         self._get_value_impl(name, selector, t)
     }
+
+    fn remove_value(&mut self, name: &'static str, selector: &Selector, t: &Time) -> Option<Value> {
+        match name {
+            "BlokVykonpInst" => self
+                .BlokVykonpInst
+                .get_mut(selector)?
+                .remove(t)
+                .flatten()
+                .map(Value::from),
+            "BlokVykonpDos" => self
+                .BlokVykonpDos
+                .get_mut(selector)?
+                .remove(t)
+                .flatten()
+                .map(Value::from),
+            "BlokVykonpMin" => self
+                .BlokVykonpMin
+                .get_mut(selector)?
+                .remove(t)
+                .flatten()
+                .map(Value::from),
+            "BlokVSAbs" => self
+                .BlokVSAbs
+                .get_mut(selector)?
+                .remove(t)
+                .flatten()
+                .map(Value::from),
+            "MarketCzkEur" => self
+                .MarketCzkEur
+                .get_mut(selector)?
+                .remove(t)
+                .flatten()
+                .map(Value::from),
+            "MarketcEle" => self
+                .MarketcEle
+                .get_mut(selector)?
+                .remove(t)
+                .flatten()
+                .map(Value::from),
+            _ => panic!("Unknown quantity {}", name),
+        }
+    }
+
+    fn remove_table(&mut self, table_name: &str) -> Vec<(&'static str, Selector, Time, Value)> {
+        let mut removed = Vec::new();
+        match table_name {
+            "BlokVykon" => {
+                for (selector, series) in std::mem::take(&mut self.BlokVykonpInst) {
+                    removed.extend(series.into_iter().filter_map(|(t, v)| {
+                        Some(("BlokVykonpInst", selector, t, Value::from(v?)))
+                    }));
+                }
+                for (selector, series) in std::mem::take(&mut self.BlokVykonpDos) {
+                    removed.extend(series.into_iter().filter_map(|(t, v)| {
+                        Some(("BlokVykonpDos", selector, t, Value::from(v?)))
+                    }));
+                }
+                for (selector, series) in std::mem::take(&mut self.BlokVykonpMin) {
+                    removed.extend(series.into_iter().filter_map(|(t, v)| {
+                        Some(("BlokVykonpMin", selector, t, Value::from(v?)))
+                    }));
+                }
+            }
+            "BlokVS" => {
+                for (selector, series) in std::mem::take(&mut self.BlokVSAbs) {
+                    removed.extend(
+                        series
+                            .into_iter()
+                            .filter_map(|(t, v)| Some(("BlokVSAbs", selector, t, Value::from(v?)))),
+                    );
+                }
+            }
+            "Market" => {
+                for (selector, series) in std::mem::take(&mut self.MarketCzkEur) {
+                    removed.extend(
+                        series.into_iter().filter_map(|(t, v)| {
+                            Some(("MarketCzkEur", selector, t, Value::from(v?)))
+                        }),
+                    );
+                }
+                for (selector, series) in std::mem::take(&mut self.MarketcEle) {
+                    removed.extend(
+                        series.into_iter().filter_map(|(t, v)| {
+                            Some(("MarketcEle", selector, t, Value::from(v?)))
+                        }),
+                    );
+                }
+            }
+            _ => {}
+        }
+        removed
+    }
+
+    fn quantity_source(&self, name: &'static str) -> Option<(&'static str, &'static str)> {
+        match name {
+            "BlokVykonpInst" => Some(("BlokVykon", "pInst")),
+            "BlokVykonpDos" => Some(("BlokVykon", "pDos")),
+            "BlokVykonpMin" => Some(("BlokVykon", "pMin")),
+            "BlokVSAbs" => Some(("BlokVS", "Abs")),
+            "MarketCzkEur" => Some(("Market", "CzkEur")),
+            "MarketcEle" => Some(("Market", "cEle")),
+            _ => None,
+        }
+    }
 }
 
 pub mod BlokVykon {
     use super::{Blok, Db, Selector};
-    use ampiato::Time;
+    use ampiato::{Error, Time};
 
     pub fn pInst(db: &Db, b: Blok, t: Time) -> f64 {
-        db.get_value("BlokVykonpInst", Selector::Blok(b), t)
+        db.get_value_as("BlokVykonpInst", Selector::Blok(b), t)
+    }
+
+    pub fn try_pInst(db: &Db, b: Blok, t: Time) -> Result<f64, Error> {
+        db.try_get_value_as("BlokVykonpInst", Selector::Blok(b), t)
     }
 
     pub fn pDos(db: &Db, b: Blok, t: Time) -> f64 {
-        db.get_value("BlokVykonpDos", Selector::Blok(b), t)
+        db.get_value_as("BlokVykonpDos", Selector::Blok(b), t)
+    }
+
+    pub fn try_pDos(db: &Db, b: Blok, t: Time) -> Result<f64, Error> {
+        db.try_get_value_as("BlokVykonpDos", Selector::Blok(b), t)
     }
 
     pub fn pMin(db: &Db, b: Blok, t: Time) -> f64 {
-        db.get_value("BlokVykonpMin", Selector::Blok(b), t)
+        db.get_value_as("BlokVykonpMin", Selector::Blok(b), t)
+    }
+
+    pub fn try_pMin(db: &Db, b: Blok, t: Time) -> Result<f64, Error> {
+        db.try_get_value_as("BlokVykonpMin", Selector::Blok(b), t)
     }
 }
 
 pub mod BlokVS {
     use super::{Blok, Db, Selector};
-    use ampiato::Time;
+    use ampiato::{Error, Time};
 
     pub fn Abs(db: &Db, b: Blok, t: Time) -> f64 {
-        db.get_value("BlokVSAbs", Selector::Blok(b), t)
+        db.get_value_as("BlokVSAbs", Selector::Blok(b), t)
+    }
+
+    pub fn try_Abs(db: &Db, b: Blok, t: Time) -> Result<f64, Error> {
+        db.try_get_value_as("BlokVSAbs", Selector::Blok(b), t)
     }
 }
 
 pub mod Market {
     use super::{Blok, Db, Selector};
-    use ampiato::Time;
+    use ampiato::{Error, Time};
 
     pub fn CzkEur(db: &Db, t: Time) -> f64 {
-        db.get_value("MarketCzkEur", Selector::Unit(()), t)
+        db.get_value_as("MarketCzkEur", Selector::Unit(()), t)
+    }
+
+    pub fn try_CzkEur(db: &Db, t: Time) -> Result<f64, Error> {
+        db.try_get_value_as("MarketCzkEur", Selector::Unit(()), t)
     }
 
     pub fn cEle(db: &Db, t: Time) -> f64 {
-        db.get_value("MarketcEle", Selector::Unit(()), t)
+        db.get_value_as("MarketcEle", Selector::Unit(()), t)
+    }
+
+    pub fn try_cEle(db: &Db, t: Time) -> Result<f64, Error> {
+        db.try_get_value_as("MarketcEle", Selector::Unit(()), t)
     }
 }
 
 pub async fn load_value_provider(pool: &sqlx::PgPool) -> ValueProvider {
     let mut vp = ValueProvider::new();
-    let rows = sqlx::query_as::<_, BlokDef>(&BlokDef::query())
+    let rows = sqlx::query_as::<_, BlokDef>(BlokDef::query())
         .fetch_all(pool)
         .await
         .unwrap();
     for row in rows {
         vp.Blok.insert(row.Jmeno.clone(), row);
     }
-    let rows = sqlx::query_as::<_, tables::BlokVykon>(&tables::BlokVykon::query())
+    let rows = sqlx::query_as::<_, tables::BlokVykon>(tables::BlokVykon::query())
         .fetch_all(pool)
         .await
         .unwrap();
     for row in rows {
         let sel = row.selector();
         for (name, value) in row.values() {
-            vp.set_value(name, sel, row.Time, *value);
+            match value {
+                Some(value) => vp.set_value(name, sel, row.Time, value).unwrap(),
+                None => vp.clear_value(name, sel, row.Time),
+            }
         }
     }
-    let rows = sqlx::query_as::<_, tables::BlokVS>(&tables::BlokVS::query())
+    let rows = sqlx::query_as::<_, tables::BlokVS>(tables::BlokVS::query())
         .fetch_all(pool)
         .await
         .unwrap();
     for row in rows {
         let sel = row.selector();
         for (name, value) in row.values() {
-            vp.set_value(name, sel, row.Time, *value);
+            match value {
+                Some(value) => vp.set_value(name, sel, row.Time, value).unwrap(),
+                None => vp.clear_value(name, sel, row.Time),
+            }
         }
     }
-    let rows = sqlx::query_as::<_, tables::Market>(&tables::Market::query())
+    let rows = sqlx::query_as::<_, tables::Market>(tables::Market::query())
         .fetch_all(pool)
         .await
         .unwrap();
     for row in rows {
         let sel = row.selector();
         for (name, value) in row.values() {
-            vp.set_value(name, sel, row.Time, *value);
+            match value {
+                Some(value) => vp.set_value(name, sel, row.Time, value).unwrap(),
+                None => vp.clear_value(name, sel, row.Time),
+            }
         }
     }
     vp
@@ -518,9 +722,9 @@ pub async fn load_value_provider(pool: &sqlx::PgPool) -> ValueProvider {
 
 pub mod prelude {
     pub use super::Blok;
-    pub use super::BlokVS::Abs;
-    pub use super::BlokVykon::{pDos, pInst, pMin};
-    pub use super::Market::{cEle, CzkEur};
+    pub use super::BlokVS::{try_Abs, Abs};
+    pub use super::BlokVykon::{pDos, pInst, pMin, try_pDos, try_pInst, try_pMin};
+    pub use super::Market::{cEle, try_CzkEur, try_cEle, CzkEur};
     pub use super::{load_value_provider, Db, Selector, Table, ValueProvider};
     pub use ampiato::ast::*;
     pub use ampiato::prelude::*;